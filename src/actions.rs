@@ -1,73 +1,174 @@
 use bevy::prelude::*;
 
+use crate::net::{OwnReplica, Remote};
+use crate::player::PlayerId;
 use crate::GameState;
 
 pub struct ActionsPlugin;
 
-// This plugin listens for keyboard input and converts the input into Actions
-// Actions can then be used as a resource in other systems to act on the player input.
+// This plugin listens for keyboard and gamepad input and converts the input into Actions
+// Each local player's Actions live on their root entity, for other systems to act on.
+// Players in an online game get their Actions from the network instead.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<Actions>()
-            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(set_actions));
+        app.add_system_set(SystemSet::on_update(GameState::Playing).with_system(set_actions));
     }
 }
 
-#[derive(Default)]
+/// Sticks have to be pushed at least this far down to count as pressing down.
+const STICK_DOWN_THRESHOLD: f32 = 0.5;
+
+#[derive(Component, Default)]
 pub struct Actions {
     pub player_movement: Option<Vec2>,
     pub combine: bool,
+    pub toggle_combine_mode: bool,
+}
+
+/// The keys one player uses. Playing alone, both sets of keys work.
+#[derive(Clone, Copy)]
+enum KeyboardLayout {
+    Both,
+    Wasd,
+    Arrows,
+}
+
+/// Which inputs control a player. With several players, the first two share
+/// the keyboard and the rest use gamepads, in the order they were connected.
+fn controls(
+    player: PlayerId,
+    player_count: usize,
+    gamepads: &Gamepads,
+) -> (Option<KeyboardLayout>, Option<Gamepad>) {
+    if player_count == 1 {
+        return (Some(KeyboardLayout::Both), gamepads.iter().next().copied());
+    }
+
+    match player.0 {
+        0 => (Some(KeyboardLayout::Wasd), None),
+        1 => (Some(KeyboardLayout::Arrows), None),
+        n => (None, gamepads.iter().nth(n - 2).copied()),
+    }
 }
 
-fn set_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<KeyCode>>) {
-    if GameControl::Left.just_released(&keyboard_input)
-        || GameControl::Left.pressed(&keyboard_input)
-        || GameControl::Down.just_released(&keyboard_input)
-        || GameControl::Down.pressed(&keyboard_input)
-        || GameControl::Right.just_released(&keyboard_input)
-        || GameControl::Right.pressed(&keyboard_input)
+#[allow(clippy::type_complexity)]
+fn set_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut players: Query<
+        (Option<&PlayerId>, &mut Actions),
+        (Or<(With<PlayerId>, With<OwnReplica>)>, Without<Remote>),
+    >,
+) {
+    let player_count = players.iter().count();
+
+    for (player, mut actions) in &mut players {
+        // online, our own player is the only one on this machine
+        let player = player.copied().unwrap_or(PlayerId(0));
+        let (layout, gamepad) = controls(player, player_count, &gamepads);
+
+        let keyboard_movement = layout
+            .and_then(|layout| keyboard_movement(actions.player_movement, layout, &keyboard_input));
+        let gamepad_movement =
+            gamepad.and_then(|gamepad| gamepad_movement(gamepad, &gamepad_buttons, &gamepad_axes));
+        actions.player_movement = keyboard_movement.or(gamepad_movement);
+
+        let button_pressed = |button_type| {
+            gamepad.map_or(false, |gamepad| {
+                gamepad_buttons.just_pressed(GamepadButton::new(gamepad, button_type))
+            })
+        };
+
+        actions.combine = layout.map_or(false, |layout| {
+            GameControl::Combine.just_pressed(layout, &keyboard_input)
+        }) || button_pressed(GamepadButtonType::South);
+        actions.toggle_combine_mode = layout.map_or(false, |layout| {
+            GameControl::ToggleCombineMode.just_pressed(layout, &keyboard_input)
+        }) || button_pressed(GamepadButtonType::Select);
+    }
+}
+
+fn keyboard_movement(
+    previous: Option<Vec2>,
+    layout: KeyboardLayout,
+    keyboard_input: &Input<KeyCode>,
+) -> Option<Vec2> {
+    if GameControl::Left.just_released(layout, keyboard_input)
+        || GameControl::Left.pressed(layout, keyboard_input)
+        || GameControl::Down.just_released(layout, keyboard_input)
+        || GameControl::Down.pressed(layout, keyboard_input)
+        || GameControl::Right.just_released(layout, keyboard_input)
+        || GameControl::Right.pressed(layout, keyboard_input)
     {
         let mut player_movement = Vec2::ZERO;
 
-        if GameControl::Down.just_released(&keyboard_input) {
-            if GameControl::Down.pressed(&keyboard_input) {
+        if GameControl::Down.just_released(layout, keyboard_input) {
+            if GameControl::Down.pressed(layout, keyboard_input) {
                 player_movement.y = -1.;
             } else {
                 player_movement.y = 0.;
             }
-        } else if GameControl::Down.just_pressed(&keyboard_input) {
+        } else if GameControl::Down.just_pressed(layout, keyboard_input) {
             player_movement.y = -1.;
         } else {
-            player_movement.y = actions.player_movement.unwrap_or(Vec2::ZERO).y;
+            player_movement.y = previous.unwrap_or(Vec2::ZERO).y;
         }
 
-        if GameControl::Right.just_released(&keyboard_input)
-            || GameControl::Left.just_released(&keyboard_input)
+        if GameControl::Right.just_released(layout, keyboard_input)
+            || GameControl::Left.just_released(layout, keyboard_input)
         {
-            if GameControl::Right.pressed(&keyboard_input) {
+            if GameControl::Right.pressed(layout, keyboard_input) {
                 player_movement.x = 1.;
-            } else if GameControl::Left.pressed(&keyboard_input) {
+            } else if GameControl::Left.pressed(layout, keyboard_input) {
                 player_movement.x = -1.;
             } else {
                 player_movement.x = 0.;
             }
-        } else if GameControl::Right.just_pressed(&keyboard_input) {
+        } else if GameControl::Right.just_pressed(layout, keyboard_input) {
             player_movement.x = 1.;
-        } else if GameControl::Left.just_pressed(&keyboard_input) {
+        } else if GameControl::Left.just_pressed(layout, keyboard_input) {
             player_movement.x = -1.;
         } else {
-            player_movement.x = actions.player_movement.unwrap_or(Vec2::ZERO).x;
+            player_movement.x = previous.unwrap_or(Vec2::ZERO).x;
         }
 
         if player_movement != Vec2::ZERO {
-            player_movement = player_movement.normalize();
-            actions.player_movement = Some(player_movement);
+            Some(player_movement.normalize())
+        } else {
+            previous
         }
     } else {
-        actions.player_movement = None;
+        None
     }
+}
 
-    actions.combine = GameControl::Combine.just_pressed(&keyboard_input);
+fn gamepad_movement(
+    gamepad: Gamepad,
+    buttons: &Input<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+) -> Option<Vec2> {
+    let pressed = |button_type| buttons.pressed(GamepadButton::new(gamepad, button_type));
+    let axis = |axis_type| {
+        axes.get(GamepadAxis::new(gamepad, axis_type))
+            .unwrap_or_default()
+    };
+
+    let mut player_movement = Vec2::new(axis(GamepadAxisType::LeftStickX), 0.0);
+    if pressed(GamepadButtonType::DPadLeft) {
+        player_movement.x = -1.;
+    } else if pressed(GamepadButtonType::DPadRight) {
+        player_movement.x = 1.;
+    }
+
+    if pressed(GamepadButtonType::DPadDown)
+        || axis(GamepadAxisType::LeftStickY) < -STICK_DOWN_THRESHOLD
+    {
+        player_movement.y = -1.;
+    }
+
+    (player_movement != Vec2::ZERO).then(|| player_movement.normalize())
 }
 
 enum GameControl {
@@ -75,57 +176,46 @@ enum GameControl {
     Left,
     Right,
     Combine,
+    ToggleCombineMode,
 }
 
 impl GameControl {
-    fn just_released(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Down => {
-                keyboard_input.just_released(KeyCode::S)
-                    || keyboard_input.just_released(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_released(KeyCode::A)
-                    || keyboard_input.just_released(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_released(KeyCode::D)
-                    || keyboard_input.just_released(KeyCode::Right)
-            }
-            GameControl::Combine => keyboard_input.just_released(KeyCode::Space),
+    fn keys(&self, layout: KeyboardLayout) -> &'static [KeyCode] {
+        use KeyboardLayout::*;
+
+        match (self, layout) {
+            (GameControl::Down, Both) => &[KeyCode::S, KeyCode::Down],
+            (GameControl::Down, Wasd) => &[KeyCode::S],
+            (GameControl::Down, Arrows) => &[KeyCode::Down],
+            (GameControl::Left, Both) => &[KeyCode::A, KeyCode::Left],
+            (GameControl::Left, Wasd) => &[KeyCode::A],
+            (GameControl::Left, Arrows) => &[KeyCode::Left],
+            (GameControl::Right, Both) => &[KeyCode::D, KeyCode::Right],
+            (GameControl::Right, Wasd) => &[KeyCode::D],
+            (GameControl::Right, Arrows) => &[KeyCode::Right],
+            (GameControl::Combine, Both | Wasd) => &[KeyCode::Space],
+            (GameControl::Combine, Arrows) => &[KeyCode::Return],
+            (GameControl::ToggleCombineMode, Both | Wasd) => &[KeyCode::Tab],
+            // the combine mode is shared, so one key for it is enough
+            (GameControl::ToggleCombineMode, Arrows) => &[],
         }
     }
 
-    fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Down => {
-                keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right)
-            }
-            GameControl::Combine => keyboard_input.pressed(KeyCode::Space),
-        }
+    fn just_released(&self, layout: KeyboardLayout, keyboard_input: &Input<KeyCode>) -> bool {
+        self.keys(layout)
+            .iter()
+            .any(|key| keyboard_input.just_released(*key))
     }
 
-    fn just_pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Down => {
-                keyboard_input.just_pressed(KeyCode::S)
-                    || keyboard_input.just_pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_pressed(KeyCode::A)
-                    || keyboard_input.just_pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_pressed(KeyCode::D)
-                    || keyboard_input.just_pressed(KeyCode::Right)
-            }
-            GameControl::Combine => keyboard_input.just_pressed(KeyCode::Space),
-        }
+    fn pressed(&self, layout: KeyboardLayout, keyboard_input: &Input<KeyCode>) -> bool {
+        self.keys(layout)
+            .iter()
+            .any(|key| keyboard_input.pressed(*key))
+    }
+
+    fn just_pressed(&self, layout: KeyboardLayout, keyboard_input: &Input<KeyCode>) -> bool {
+        self.keys(layout)
+            .iter()
+            .any(|key| keyboard_input.just_pressed(*key))
     }
 }
@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::*;
+
+use crate::actions::Actions;
+use crate::cluster::Cluster;
+use crate::doodad::{Doodad, DoodadKind};
+use crate::level::{CurrentLevel, Level};
+use crate::loading::MeshAssets;
+use crate::net::{self, NetRole};
+use crate::player::{self, Player};
+use crate::settings::{AttachmentMode, Settings};
+use crate::soft::{self, SoftAttached};
+use crate::GameState;
+
+pub struct AiPlugin;
+
+/// This plugin adds computer-controlled rival clusters to the level. They
+/// move just like players, by setting their own [`Actions`]: they go after
+/// loose doodads and smaller clusters, and keep away from bigger ones.
+/// Whenever a cluster touches a rival much smaller than itself, it takes over
+/// all of the smaller one's pieces.
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_rivals))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(steer_rivals)
+                    .with_system(absorb_smaller_clusters),
+            );
+    }
+}
+
+/// A cluster can absorb another one at least this many times smaller than itself.
+const ABSORB_RATIO: f32 = 1.5;
+/// How far away rivals notice bigger clusters, and run from them.
+const FLEE_RADIUS: f32 = 250.0;
+/// How far away rivals notice smaller clusters to chase.
+const CHASE_RADIUS: f32 = 400.0;
+/// How far away rivals notice loose doodads to collect.
+const SEEK_RADIUS: f32 = 600.0;
+/// Rivals stop rolling once they are this close to their target, horizontally.
+const ARRIVE_DISTANCE: f32 = 10.0;
+/// The radius of a bare root.
+const ROOT_RADIUS: f32 = 15.0;
+/// Roughly how much each piece adds to a cluster's reach, by the square root
+/// of the number of pieces, since they pile up around the root.
+const PIECE_REACH: f32 = 12.0;
+
+/// A cluster driven by the computer instead of someone's input.
+#[derive(Component)]
+pub struct Ai;
+
+fn spawn_rivals(
+    mut commands: Commands,
+    net_role: Option<Res<NetRole>>,
+    meshes: Res<MeshAssets>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        // clients only show the rivals the host sends them
+        Some(level) if !net::is_client(&net_role) => level,
+        _ => return,
+    };
+
+    for position in &level.rivals {
+        let root = player::spawn_cluster_root(&mut commands, &meshes, meshes.ai.clone(), *position);
+        commands.entity(root).insert(Ai);
+    }
+}
+
+/// How many pieces each cluster has, counting the root, by root.
+fn cluster_sizes<'a>(
+    clusters: impl Iterator<Item = (Entity, &'a Cluster)>,
+    soft_pieces: impl Iterator<Item = &'a SoftAttached>,
+) -> HashMap<Entity, usize> {
+    let mut sizes: HashMap<_, _> = clusters
+        .map(|(root, cluster)| (root, 1 + cluster.piece_count()))
+        .collect();
+
+    for piece in soft_pieces {
+        if let Some(size) = sizes.get_mut(&piece.root()) {
+            *size += 1;
+        }
+    }
+
+    sizes
+}
+
+/// The closest of `positions` within `radius`, and how far it is.
+fn nearest(
+    position: Vec2,
+    positions: impl IntoIterator<Item = Vec2>,
+    radius: f32,
+) -> Option<(Vec2, f32)> {
+    positions
+        .into_iter()
+        .map(|other| (other, position.distance(other)))
+        .filter(|(_, distance)| *distance <= radius)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// Roughly how far a cluster of this size reaches from its center.
+fn reach(size: usize) -> f32 {
+    ROOT_RADIUS + PIECE_REACH * (size as f32).sqrt()
+}
+
+fn can_absorb(size: usize, other_size: usize) -> bool {
+    // a bare root has nothing to take
+    other_size > 1 && size as f32 >= other_size as f32 * ABSORB_RATIO
+}
+
+/// Pick what each rival goes for, and press the same controls a player would.
+fn steer_rivals(
+    mut rivals: Query<(Entity, &GlobalTransform, &mut Actions), With<Ai>>,
+    clusters: Query<(Entity, &GlobalTransform, &Cluster), (With<Player>, Without<Parent>)>,
+    soft_pieces: Query<&SoftAttached>,
+    doodads: Query<&GlobalTransform, With<Doodad>>,
+) {
+    let sizes = cluster_sizes(
+        clusters.iter().map(|(root, _, cluster)| (root, cluster)),
+        soft_pieces.iter(),
+    );
+
+    for (rival, transform, mut actions) in &mut rivals {
+        let position = transform.translation().truncate();
+        let size = sizes.get(&rival).copied().unwrap_or(1);
+
+        let other_clusters = |keep: fn(usize, usize) -> bool| {
+            let sizes = &sizes;
+            clusters
+                .iter()
+                .filter(move |(other, _, _)| {
+                    *other != rival && keep(size, sizes.get(other).copied().unwrap_or(1))
+                })
+                .map(|(_, transform, _)| transform.translation().truncate())
+        };
+
+        let threat = nearest(
+            position,
+            other_clusters(|size, other_size| other_size > size),
+            FLEE_RADIUS,
+        );
+        let prey = nearest(position, other_clusters(can_absorb), CHASE_RADIUS);
+        let food = nearest(
+            position,
+            doodads
+                .iter()
+                .map(|transform| transform.translation().truncate()),
+            SEEK_RADIUS,
+        );
+
+        // Running away matters more than anything else, then going after
+        // smaller clusters, which are worth more than single doodads.
+        let direction = if let Some((threat, _)) = threat {
+            (position.x - threat.x).signum()
+        } else if let Some((target, _)) = prey.or(food) {
+            let offset = target.x - position.x;
+            if offset.abs() > ARRIVE_DISTANCE {
+                offset.signum()
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        };
+
+        actions.player_movement = (direction != 0.0).then(|| Vec2::new(direction, 0.0));
+        actions.combine = food.map_or(false, |(_, distance)| distance <= reach(size));
+    }
+}
+
+/// When a rival touches a cluster much smaller than itself, or a player
+/// touches a much smaller rival, the bigger one takes all of the smaller
+/// one's pieces, leaving only its bare root.
+#[allow(clippy::type_complexity)]
+fn absorb_smaller_clusters(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    rivals: Query<(Entity, &GlobalTransform, &Collider), With<Ai>>,
+    clusters: Query<
+        (Entity, &GlobalTransform, &Cluster, Option<&Ai>),
+        (With<Player>, Without<Parent>),
+    >,
+    attached: Query<(Entity, &Parent, &GlobalTransform, &DoodadKind), (With<Player>, With<Parent>)>,
+    soft_pieces: Query<(Entity, &SoftAttached, &GlobalTransform)>,
+    settings: Res<Settings>,
+) {
+    let sizes = cluster_sizes(
+        clusters.iter().map(|(root, _, cluster, _)| (root, cluster)),
+        soft_pieces.iter().map(|(_, piece, _)| piece),
+    );
+
+    // Only rivals need to look for contacts, since at least one side of
+    // every pair has to be a rival.
+    let mut touching = HashSet::new();
+    for (rival, transform, collider) in &rivals {
+        let transform = transform.compute_transform();
+        // assume axis is always the same, since this is 2D
+        let (_axis, shape_rot) = transform.rotation.to_axis_angle();
+        let shape_pos = transform.translation.truncate();
+        let filter = QueryFilter::only_dynamic().exclude_collider(rival);
+
+        rapier_context.intersections_with_shape(shape_pos, shape_rot, collider, filter, |other| {
+            let other = match soft_pieces.get(other) {
+                Ok((_, piece, _)) => piece.root(),
+                Err(_) => other,
+            };
+            if other != rival && sizes.contains_key(&other) {
+                touching.insert((rival.min(other), rival.max(other)));
+            }
+            true
+        });
+    }
+
+    // nothing gets absorbed more than once, or absorbed while absorbing
+    let mut involved = HashSet::new();
+
+    for (a, b) in touching {
+        let (bigger, smaller) = match (sizes[&a], sizes[&b]) {
+            (a_size, b_size) if can_absorb(a_size, b_size) => (a, b),
+            (a_size, b_size) if can_absorb(b_size, a_size) => (b, a),
+            _ => continue,
+        };
+
+        if involved.contains(&bigger) || involved.contains(&smaller) {
+            continue;
+        }
+
+        let (bigger_transform, smaller_is_ai) = match (clusters.get(bigger), clusters.get(smaller))
+        {
+            (Ok((_, transform, _, _)), Ok((_, _, _, ai))) => (transform, ai.is_some()),
+            _ => continue,
+        };
+        involved.insert(bigger);
+        involved.insert(smaller);
+
+        log::info!(
+            "{} {smaller:?} lost its {} pieces to {bigger:?}",
+            if smaller_is_ai { "rival" } else { "player" },
+            sizes[&smaller] - 1
+        );
+
+        for (piece, parent, transform, kind) in &attached {
+            if parent.get() != smaller {
+                continue;
+            }
+
+            let local = player::attached_transform(bigger_transform, transform);
+            match settings.attachment_mode {
+                AttachmentMode::Rigid => {
+                    commands.entity(smaller).remove_children(&[piece]);
+                    commands.entity(bigger).add_child(piece);
+                    commands
+                        .entity(piece)
+                        .insert(local)
+                        // merged into the new cluster's collider by the cluster
+                        // plugin, held on by its root since nothing records
+                        // where the two clusters met
+                        .insert(kind.collider());
+                }
+                AttachmentMode::Soft => {
+                    let velocity = Velocity::default();
+                    player::release_doodad(
+                        &mut commands,
+                        smaller,
+                        piece,
+                        *kind,
+                        transform,
+                        velocity,
+                    );
+                    soft::attach_soft(&mut commands, bigger, bigger_transform, piece, local);
+                }
+            }
+        }
+
+        for (piece, soft_piece, transform) in &soft_pieces {
+            if soft_piece.root() != smaller {
+                continue;
+            }
+
+            let local = player::attached_transform(bigger_transform, transform);
+            match settings.attachment_mode {
+                // attached like any freshly absorbed doodad
+                AttachmentMode::Rigid => {
+                    commands
+                        .entity(piece)
+                        .remove::<ImpulseJoint>()
+                        .remove::<SoftAttached>()
+                        .remove::<RigidBody>()
+                        .insert(Player)
+                        .insert(local);
+                    commands.entity(bigger).add_child(piece);
+                }
+                // the new joint replaces the old one
+                AttachmentMode::Soft => {
+                    soft::attach_soft(&mut commands, bigger, bigger_transform, piece, local)
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,14 @@
+use std::time::Duration;
+
 use bevy::prelude::*;
 use bevy_kira_audio::prelude::*;
+use bevy_rapier2d::prelude::*;
 
-use crate::actions::Actions;
-use crate::loading::AudioAssets;
+use crate::cluster::Cluster;
+use crate::loading::{AudioAssets, MusicAssets};
+use crate::player::{self, Player, PlayerId};
+use crate::settings::Settings;
+use crate::soft::SoftAttached;
 use crate::GameState;
 
 pub struct InternalAudioPlugin;
@@ -11,43 +17,284 @@ pub struct InternalAudioPlugin;
 impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(AudioPlugin)
-            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_audio))
+            .add_audio_channel::<MenuMusicChannel>()
+            .add_audio_channel::<BaseStemChannel>()
+            .add_audio_channel::<RhythmStemChannel>()
+            .add_audio_channel::<LeadStemChannel>()
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(start_menu_music))
+            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(control_menu_music))
+            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(stop_menu_music))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(start_audio)
+                    .with_system(start_music_stems),
+            )
             .add_system_set(
-                SystemSet::on_update(GameState::Playing).with_system(control_flying_sound),
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(control_rolling_sound)
+                    .with_system(control_music_stems),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(stop_audio)
+                    .with_system(stop_music_stems),
             );
     }
 }
 
-struct FlyingAudio(Handle<AudioInstance>);
+/// Volume of the rolling sound at full angular speed.
+const ROLLING_VOLUME: f64 = 0.3;
+/// How quickly (per second) the rolling volume approaches its target,
+/// so that leaving the ground fades out instead of cutting off.
+const ROLLING_FADE_RATE: f64 = 4.0;
+/// Playback rate range of the rolling sound, from standstill to full speed.
+const ROLLING_MIN_RATE: f64 = 0.6;
+const ROLLING_MAX_RATE: f64 = 1.6;
+
+struct RollingAudio {
+    handle: Handle<AudioInstance>,
+    /// The current (unscaled) volume, smoothed towards the target each frame.
+    volume: f64,
+}
 
 fn start_audio(mut commands: Commands, audio_assets: Res<AudioAssets>, audio: Res<Audio>) {
-    audio.pause();
+    // The sound loops forever and is silenced by volume rather than pausing,
+    // so the loop doesn't restart audibly every time we touch down.
     let handle = audio
         .play(audio_assets.flying.clone())
         .looped()
-        .with_volume(0.3)
+        .with_volume(0.0)
         .handle();
-    commands.insert_resource(FlyingAudio(handle));
+    commands.insert_resource(RollingAudio {
+        handle,
+        volume: 0.0,
+    });
+}
+
+fn control_rolling_sound(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    rapier_context: Res<RapierContext>,
+    mut audio: ResMut<RollingAudio>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    root_player: Query<&Velocity, (With<PlayerId>, Without<Parent>)>,
+    player_colliders: Query<(Entity, Option<&Parent>), With<Player>>,
+) {
+    // with several players, follow whoever is rolling fastest
+    let speed = root_player
+        .iter()
+        .map(|velocity| (velocity.angvel.abs() / player::MAX_ANGULAR_SPEED).min(1.0) as f64)
+        .fold(0.0, f64::max);
+
+    // The player only collides with level geometry, so any contact means we're rolling on it.
+    // Computer-controlled clusters don't make any rolling sound.
+    let grounded = player_colliders
+        .iter()
+        .filter(|(entity, parent)| root_player.get(parent.map_or(*entity, Parent::get)).is_ok())
+        .any(|(entity, _)| {
+            rapier_context
+                .contacts_with(entity)
+                .any(|contact| contact.has_any_active_contacts())
+        });
+
+    let target = if grounded {
+        speed * ROLLING_VOLUME
+    } else {
+        0.0
+    };
+    let blend = (ROLLING_FADE_RATE * time.delta_seconds_f64()).min(1.0);
+    audio.volume += (target - audio.volume) * blend;
+
+    if let Some(instance) = audio_instances.get_mut(&audio.handle) {
+        let rate = ROLLING_MIN_RATE + (ROLLING_MAX_RATE - ROLLING_MIN_RATE) * speed;
+        instance.set_playback_rate(rate, AudioTween::default());
+        instance.set_volume(audio.volume * settings.audio.sfx(), AudioTween::default());
+    }
+}
+
+/// Playing can be left and entered again, e.g. from the editor, which would
+/// otherwise start another copy of the loop each time.
+fn stop_audio(
+    mut commands: Commands,
+    audio: Res<RollingAudio>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if let Some(instance) = audio_instances.get_mut(&audio.handle) {
+        instance.stop(AudioTween::default());
+    }
+    commands.remove_resource::<RollingAudio>();
+}
+
+pub struct MenuMusicChannel;
+pub struct BaseStemChannel;
+pub struct RhythmStemChannel;
+pub struct LeadStemChannel;
+
+const MENU_MUSIC_VOLUME: f64 = 0.5;
+const STEM_VOLUME: f64 = 0.4;
+/// How long menu and gameplay music take to fade into each other.
+const MUSIC_CROSSFADE: Duration = Duration::from_millis(1500);
+/// How quickly (per second) stems approach their target volume.
+const STEM_FADE_RATE: f64 = 0.5;
+
+/// Cluster sizes over which each stem fades in, from silent to full volume.
+const RHYTHM_STEM_RANGE: (f64, f64) = (5.0, 15.0);
+const LEAD_STEM_RANGE: (f64, f64) = (20.0, 40.0);
+
+struct MenuMusic(Handle<AudioInstance>);
+
+/// The gameplay music is split into stems that all loop in sync, so that layers
+/// can be faded in and out without losing their place in the song.
+struct MusicStems {
+    base: Stem,
+    rhythm: Stem,
+    lead: Stem,
+}
+
+struct Stem {
+    handle: Handle<AudioInstance>,
+    /// The current (unscaled) volume, smoothed towards the target each frame.
+    volume: f64,
+}
+
+impl Stem {
+    fn new(handle: Handle<AudioInstance>) -> Self {
+        Self {
+            handle,
+            volume: 0.0,
+        }
+    }
+}
+
+fn start_menu_music(
+    mut commands: Commands,
+    music_assets: Res<MusicAssets>,
+    channel: Res<AudioChannel<MenuMusicChannel>>,
+    settings: Res<Settings>,
+) {
+    let handle = channel
+        .play(music_assets.menu.clone())
+        .looped()
+        .with_volume(MENU_MUSIC_VOLUME * settings.audio.music())
+        .fade_in(AudioTween::linear(MUSIC_CROSSFADE))
+        .handle();
+    commands.insert_resource(MenuMusic(handle));
+}
+
+fn control_menu_music(
+    settings: Res<Settings>,
+    music: Res<MenuMusic>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    if let Some(instance) = audio_instances.get_mut(&music.0) {
+        instance.set_volume(
+            MENU_MUSIC_VOLUME * settings.audio.music(),
+            AudioTween::default(),
+        );
+    }
+}
+
+fn stop_menu_music(
+    mut commands: Commands,
+    music: Res<MenuMusic>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if let Some(instance) = audio_instances.get_mut(&music.0) {
+        instance.stop(AudioTween::linear(MUSIC_CROSSFADE));
+    }
+    commands.remove_resource::<MenuMusic>();
+}
+
+fn start_music_stems(
+    mut commands: Commands,
+    music_assets: Res<MusicAssets>,
+    base: Res<AudioChannel<BaseStemChannel>>,
+    rhythm: Res<AudioChannel<RhythmStemChannel>>,
+    lead: Res<AudioChannel<LeadStemChannel>>,
+) {
+    // Everything starts silent and is faded in by `control_music_stems`,
+    // which also gives us the crossfade from the menu music for free.
+    commands.insert_resource(MusicStems {
+        base: Stem::new(
+            base.play(music_assets.base.clone())
+                .looped()
+                .with_volume(0.0)
+                .handle(),
+        ),
+        rhythm: Stem::new(
+            rhythm
+                .play(music_assets.rhythm.clone())
+                .looped()
+                .with_volume(0.0)
+                .handle(),
+        ),
+        lead: Stem::new(
+            lead.play(music_assets.lead.clone())
+                .looped()
+                .with_volume(0.0)
+                .handle(),
+        ),
+    });
+}
+
+/// Linearly ramp from 0.0 to 1.0 as `value` goes from `start` to `end`.
+fn ramp(value: f64, (start, end): (f64, f64)) -> f64 {
+    ((value - start) / (end - start)).clamp(0.0, 1.0)
+}
+
+// TODO: also fade the stems in as a round timer runs low, once the game has one
+fn control_music_stems(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut stems: ResMut<MusicStems>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    roots: Query<(Entity, &Cluster), (With<PlayerId>, Without<Parent>)>,
+    soft_pieces: Query<&SoftAttached>,
+) {
+    // with several players, follow whoever is biggest, leaving out the AI
+    let cluster_size = roots
+        .iter()
+        .map(|(root, cluster)| {
+            let soft_count = soft_pieces
+                .iter()
+                .filter(|piece| piece.root() == root)
+                .count();
+            1 + cluster.piece_count() + soft_count
+        })
+        .max()
+        .unwrap_or(0) as f64;
+    let blend = (STEM_FADE_RATE * time.delta_seconds_f64()).min(1.0);
+
+    let MusicStems { base, rhythm, lead } = &mut *stems;
+    for (stem, target) in [
+        (base, 1.0),
+        (rhythm, ramp(cluster_size, RHYTHM_STEM_RANGE)),
+        (lead, ramp(cluster_size, LEAD_STEM_RANGE)),
+    ] {
+        stem.volume += (target - stem.volume) * blend;
+
+        if let Some(instance) = audio_instances.get_mut(&stem.handle) {
+            instance.set_volume(
+                stem.volume * STEM_VOLUME * settings.audio.music(),
+                AudioTween::default(),
+            );
+        }
+    }
 }
 
-fn control_flying_sound(
-    actions: Res<Actions>,
-    audio: Res<FlyingAudio>,
+fn stop_music_stems(
+    mut commands: Commands,
+    stems: Res<MusicStems>,
     mut audio_instances: ResMut<Assets<AudioInstance>>,
 ) {
-    if let Some(instance) = audio_instances.get_mut(&audio.0) {
-        match instance.state() {
-            PlaybackState::Paused { .. } => {
-                if actions.player_movement.is_some() {
-                    instance.resume(AudioTween::default());
-                }
-            }
-            PlaybackState::Playing { .. } => {
-                if actions.player_movement.is_none() {
-                    instance.pause(AudioTween::default());
-                }
-            }
-            _ => {}
+    for stem in [&stems.base, &stems.rhythm, &stems.lead] {
+        if let Some(instance) = audio_instances.get_mut(&stem.handle) {
+            instance.stop(AudioTween::linear(MUSIC_CROSSFADE));
         }
     }
+    commands.remove_resource::<MusicStems>();
 }
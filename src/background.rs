@@ -0,0 +1,215 @@
+use bevy::prelude::*;
+use bevy::sprite::Mesh2dHandle;
+use bevy::transform::TransformSystem;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{self, PlayerCamera};
+use crate::level::{CurrentLevel, Level};
+use crate::net::{self, NetRole};
+use crate::player::PlayerId;
+use crate::settings::Settings;
+use crate::GameState;
+
+pub struct BackgroundPlugin;
+
+/// This plugin draws the parallax background layers defined by the current level.
+/// Each player's camera gets its own copy, since the layers move with the camera.
+impl Plugin for BackgroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(build_background_meshes)
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_background))
+            // Scroll after everything else has moved the camera this frame,
+            // otherwise the layers lag behind and jitter.
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                scroll_background.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// The width of one repetition of a layer's pattern.
+const TILE_WIDTH: f32 = 1024.0;
+/// How many tiles to draw on each side of the center one, enough to cover a wide screen.
+const TILE_REPEATS: i32 = 2;
+/// Layers are drawn behind the level, which starts at `LEVEL_Z`.
+const BACKGROUND_Z: f32 = 1.0;
+const LAYER_Z_STEP: f32 = 0.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundLayer {
+    /// How much this layer moves along with the world: `0.0` stays fixed to
+    /// the camera (infinitely far away) and `1.0` moves with the level.
+    pub parallax: f32,
+    pub color: Color,
+    /// Vertical offset of the layer's baseline, relative to the camera at rest.
+    #[serde(default)]
+    pub offset_y: f32,
+    pub shapes: LayerShapes,
+    /// Seed for the procedurally placed shapes, so layers look the same every time.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LayerShapes {
+    /// Overlapping circles, mostly below the baseline, like rolling hills.
+    Hills {
+        count: usize,
+        min_radius: f32,
+        max_radius: f32,
+    },
+    /// Rectangles standing on the baseline, like a city skyline.
+    Blocks {
+        count: usize,
+        min_width: f32,
+        max_width: f32,
+        min_height: f32,
+        max_height: f32,
+    },
+}
+
+struct BackgroundMeshes {
+    quad: Mesh2dHandle,
+    circle: Mesh2dHandle,
+}
+
+#[derive(Component)]
+struct ParallaxLayer {
+    /// The player whose camera shows this layer.
+    player: PlayerId,
+    parallax: f32,
+    offset_y: f32,
+}
+
+fn build_background_meshes(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(BackgroundMeshes {
+        quad: meshes.add(shape::Quad::new(Vec2::ONE).into()).into(),
+        circle: meshes.add(shape::Circle::new(0.5).into()).into(),
+    });
+}
+
+fn spawn_background(
+    mut commands: Commands,
+    background_meshes: Res<BackgroundMeshes>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    settings: Res<Settings>,
+    net_role: Option<Res<NetRole>>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        Some(level) => level,
+        None => return,
+    };
+
+    for (i, layer) in level.background.iter().enumerate() {
+        let material = materials.add(ColorMaterial::from(layer.color));
+        // each layer is one tile's worth of shapes, repeated side by side
+        let shapes = layer_shapes(layer, &background_meshes);
+
+        for id in 0..net::view_count(&settings, &net_role) {
+            let player = PlayerId(id);
+            // render layers aren't inherited, so every shape needs one
+            let view_layer = camera::view_layer(player);
+
+            commands
+                .spawn_bundle(SpatialBundle::from_transform(Transform::from_xyz(
+                    0.0,
+                    0.0,
+                    BACKGROUND_Z + i as f32 * LAYER_Z_STEP,
+                )))
+                .insert(ParallaxLayer {
+                    player,
+                    parallax: layer.parallax,
+                    offset_y: layer.offset_y,
+                })
+                .with_children(|parent| {
+                    for tile in -TILE_REPEATS..=TILE_REPEATS {
+                        let tile_offset = Vec3::new(tile as f32 * TILE_WIDTH, 0.0, 0.0);
+
+                        for (mesh, transform) in &shapes {
+                            parent
+                                .spawn_bundle(ColorMesh2dBundle {
+                                    mesh: mesh.clone(),
+                                    material: material.clone(),
+                                    transform: Transform {
+                                        translation: transform.translation + tile_offset,
+                                        ..*transform
+                                    },
+                                    ..default()
+                                })
+                                .insert(view_layer);
+                        }
+                    }
+                });
+        }
+    }
+}
+
+/// Generate the shapes for a single tile of a layer, relative to the layer's baseline.
+fn layer_shapes(
+    layer: &BackgroundLayer,
+    background_meshes: &BackgroundMeshes,
+) -> Vec<(Mesh2dHandle, Transform)> {
+    let mut rng = StdRng::seed_from_u64(layer.seed);
+
+    match layer.shapes {
+        LayerShapes::Hills {
+            count,
+            min_radius,
+            max_radius,
+        } => {
+            (0..count)
+                .map(|_| {
+                    let radius = between(&mut rng, min_radius, max_radius);
+                    let x = rng.gen_range(0.0..TILE_WIDTH) - TILE_WIDTH / 2.0;
+                    let transform = Transform::from_xyz(x, -radius * 0.4, 0.0)
+                        .with_scale(Vec3::new(radius * 2.0, radius * 2.0, 1.0));
+                    (background_meshes.circle.clone(), transform)
+                })
+                .collect()
+        }
+        LayerShapes::Blocks {
+            count,
+            min_width,
+            max_width,
+            min_height,
+            max_height,
+        } => (0..count)
+            .map(|_| {
+                let width = between(&mut rng, min_width, max_width);
+                let height = between(&mut rng, min_height, max_height);
+                let x = rng.gen_range(0.0..TILE_WIDTH) - TILE_WIDTH / 2.0;
+                let transform = Transform::from_xyz(x, height / 2.0, 0.0)
+                    .with_scale(Vec3::new(width, height, 1.0));
+                (background_meshes.quad.clone(), transform)
+            })
+            .collect(),
+    }
+}
+
+/// A random value between two bounds given in either order, since level
+/// files are edited by hand and a swapped pair shouldn't crash the game.
+fn between(rng: &mut StdRng, a: f32, b: f32) -> f32 {
+    rng.gen_range(a.min(b)..=a.max(b))
+}
+
+fn scroll_background(
+    cameras: Query<(&PlayerCamera, &Transform), Without<ParallaxLayer>>,
+    mut layers: Query<(&ParallaxLayer, &mut Transform)>,
+) {
+    for (layer, mut transform) in &mut layers {
+        let camera = match cameras.iter().find(|(camera, _)| camera.0 == layer.player) {
+            Some((_, camera)) => camera.translation.truncate(),
+            None => continue,
+        };
+
+        // The layer appears to move by `parallax` times the camera movement,
+        // wrapping around every tile so it never runs out.
+        let scrolled = (camera.x * layer.parallax).rem_euclid(TILE_WIDTH);
+        transform.translation.x = camera.x - scrolled;
+        transform.translation.y = camera.y * (1.0 - layer.parallax) + layer.offset_y;
+    }
+}
@@ -0,0 +1,55 @@
+//! Runs the game headless with thousands of doodads and a huge cluster,
+//! writing per-frame timings to a CSV file.
+//!
+//! Usage: `cargo run --release --bin stress -- [doodads] [cluster size] [frames] [output.csv]`
+
+use std::env;
+use std::str::FromStr;
+
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::prelude::*;
+use bevy::render::settings::WgpuSettings;
+use bevy::winit::WinitPlugin;
+
+use clusterjunk::{GamePlugin, Settings, StressTest, StressTestPlugin};
+
+const USAGE: &str = "usage: stress [doodads] [cluster size] [frames] [output.csv]";
+
+fn main() {
+    let defaults = StressTest::default();
+    let mut args = env::args().skip(1);
+
+    let stress_test = StressTest {
+        doodads: next_arg(&mut args, "doodad count").unwrap_or(defaults.doodads),
+        cluster_size: next_arg(&mut args, "cluster size").unwrap_or(defaults.cluster_size),
+        frames: next_arg(&mut args, "frame count").unwrap_or(defaults.frames),
+        output: next_arg(&mut args, "output path").unwrap_or(defaults.output),
+    };
+
+    App::new()
+        // no window or GPU needed
+        .insert_resource(WgpuSettings {
+            backends: None,
+            ..default()
+        })
+        // don't let the player's own settings affect the results
+        .insert_resource(Settings::default())
+        .insert_resource(stress_test)
+        .add_plugins_with(DefaultPlugins, |group| group.disable::<WinitPlugin>())
+        .add_plugin(ScheduleRunnerPlugin)
+        .add_plugin(GamePlugin)
+        .add_plugin(StressTestPlugin)
+        .run();
+}
+
+/// Parse the next argument, if there is one, exiting with the usage if it's invalid.
+fn next_arg<T: FromStr>(args: &mut impl Iterator<Item = String>, name: &str) -> Option<T> {
+    let arg = args.next()?;
+    match arg.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("invalid {name}: {arg}\n{USAGE}");
+            std::process::exit(2);
+        }
+    }
+}
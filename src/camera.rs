@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::render::view::RenderLayers;
+use bevy::ui::UiCameraConfig;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+use crate::net::{self, NetRole, OwnReplica};
+use crate::physics::{CollideGroups, Impact};
+use crate::player::{DoodadAbsorbed, Player, PlayerId};
+use crate::settings::Settings;
+use crate::GameState;
+
+pub struct CameraPlugin;
+
+/// This plugin owns the game cameras, one for each local player, which
+/// follow their player around and shake for impact feedback. With several
+/// players, the window is split between their cameras.
+/// The shake intensity is scaled by [`Settings::screen_shake`].
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_camera)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(spawn_player_cameras),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(split_screen)
+                    .with_system(follow_player.before(shake_camera))
+                    .with_system(add_impact_trauma)
+                    .with_system(add_absorb_trauma)
+                    .with_system(shake_camera),
+            );
+    }
+}
+
+/// A player's view of the game, following the player with the same id.
+#[derive(Component)]
+pub struct PlayerCamera(pub PlayerId);
+
+/// The render layer for things only one player's camera should show, like
+/// their own background. Everything else is on the default layer, which
+/// every camera shows.
+pub fn view_layer(player: PlayerId) -> RenderLayers {
+    RenderLayers::layer(player.0 as u8 + 1)
+}
+
+fn camera_layers(player: PlayerId) -> RenderLayers {
+    RenderLayers::default().with(player.0 as u8 + 1)
+}
+
+/// How quickly (per second) the camera catches up with the player.
+const FOLLOW_RATE: f32 = 4.0;
+
+/// Impacts below this impulse don't shake the camera, and anything above
+/// `IMPACT_IMPULSE_MAX` adds `IMPACT_TRAUMA_MAX`.
+const IMPACT_IMPULSE_MIN: f32 = 100.0;
+const IMPACT_IMPULSE_MAX: f32 = 600.0;
+const IMPACT_TRAUMA_MAX: f32 = 0.6;
+
+/// Absorbing more than this many doodads at once counts as a "large" absorption.
+const ABSORB_COUNT_MIN: usize = 3;
+const ABSORB_TRAUMA_PER_DOODAD: f32 = 0.05;
+
+/// How much trauma goes away per second.
+const TRAUMA_DECAY: f32 = 1.5;
+const MAX_SHAKE_OFFSET: f32 = 12.0;
+const MAX_SHAKE_ANGLE: f32 = 0.03;
+
+/// Accumulated "trauma" which shakes the camera, from `0.0` to `1.0`.
+/// The shake intensity grows with the square of the trauma, so small
+/// bumps are subtle while big hits are obvious.
+#[derive(Component, Default)]
+pub struct CameraShake {
+    pub trauma: f32,
+    /// The offset applied last frame, so it can be undone before applying
+    /// the next one without disturbing anything else moving the camera.
+    offset: Vec2,
+    angle: f32,
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+}
+
+/// The first player's camera also shows the menu, so it exists from the start.
+fn spawn_camera(mut commands: Commands) {
+    let player = PlayerId(0);
+    commands
+        .spawn_bundle(Camera2dBundle::default())
+        .insert(CameraShake::default())
+        .insert(PlayerCamera(player))
+        .insert(camera_layers(player));
+}
+
+fn spawn_player_cameras(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    net_role: Option<Res<NetRole>>,
+) {
+    for id in 1..net::view_count(&settings, &net_role) {
+        let player = PlayerId(id);
+        commands
+            .spawn_bundle(Camera2dBundle {
+                camera: Camera {
+                    priority: id as isize,
+                    ..default()
+                },
+                // the first camera clears the whole window for everyone
+                camera_2d: Camera2d {
+                    clear_color: ClearColorConfig::None,
+                },
+                ..default()
+            })
+            .insert(UiCameraConfig { show_ui: false })
+            .insert(CameraShake::default())
+            .insert(PlayerCamera(player))
+            .insert(camera_layers(player));
+    }
+}
+
+/// Give each camera its part of the window: side by side for two players,
+/// and a quarter each for three or four.
+fn split_screen(windows: Res<Windows>, mut cameras: Query<(&PlayerCamera, &mut Camera)>) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let grid = match cameras.iter().count() {
+        0 | 1 => None,
+        2 => Some(UVec2::new(2, 1)),
+        _ => Some(UVec2::new(2, 2)),
+    };
+    let window_size = UVec2::new(window.physical_width(), window.physical_height());
+
+    for (PlayerCamera(player), mut camera) in &mut cameras {
+        let viewport = grid.and_then(|grid| {
+            let size = window_size / grid;
+            // e.g. while minimized
+            if size.min_element() == 0 {
+                return None;
+            }
+
+            let cell = UVec2::new(player.0 as u32 % grid.x, player.0 as u32 / grid.x);
+            Some((cell * size, size))
+        });
+
+        // only touch the camera when the layout changes
+        let current = camera
+            .viewport
+            .as_ref()
+            .map(|viewport| (viewport.physical_position, viewport.physical_size));
+        if current != viewport {
+            camera.viewport = viewport.map(|(position, size)| Viewport {
+                physical_position: position,
+                physical_size: size,
+                depth: 0.0..1.0,
+            });
+        }
+    }
+}
+
+fn follow_player(
+    time: Res<Time>,
+    players: Query<(&PlayerId, &GlobalTransform), (With<Player>, Without<Parent>)>,
+    own_replica: Query<&GlobalTransform, With<OwnReplica>>,
+    mut cameras: Query<(&PlayerCamera, &mut Transform)>,
+) {
+    let blend = (FOLLOW_RATE * time.delta_seconds()).min(1.0);
+
+    for (PlayerCamera(player), mut transform) in &mut cameras {
+        let target = players
+            .iter()
+            .find(|(id, _)| *id == player)
+            .map(|(_, target)| target)
+            // online, the first camera follows our player as the host sees it
+            .or_else(|| own_replica.get_single().ok().filter(|_| player.0 == 0));
+        let target = match target {
+            Some(target) => target.translation().truncate(),
+            None => continue,
+        };
+
+        let position = transform.translation.truncate();
+        let position = position + (target - position) * blend;
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+fn add_impact_trauma(
+    mut impacts: EventReader<Impact>,
+    mut cameras: Query<(&PlayerCamera, &mut CameraShake)>,
+    players: Query<Option<&Parent>, With<Player>>,
+    roots: Query<&PlayerId>,
+    groups: Query<&CollisionGroups>,
+) {
+    let player_of = |entity| {
+        let parent = players.get(entity).ok()?;
+        roots.get(parent.map_or(entity, Parent::get)).ok()
+    };
+    let is_level = |entity| {
+        groups.get(entity).map_or(false, |groups| {
+            groups.memberships & CollideGroups::LEVEL.bits() != 0
+        })
+    };
+
+    for impact in impacts.iter() {
+        let [a, b] = impact.colliders;
+        let player = match (player_of(a), player_of(b)) {
+            (Some(player), None) if is_level(b) => player,
+            (None, Some(player)) if is_level(a) => player,
+            _ => continue,
+        };
+
+        let strength = ((impact.impulse - IMPACT_IMPULSE_MIN)
+            / (IMPACT_IMPULSE_MAX - IMPACT_IMPULSE_MIN))
+            .clamp(0.0, 1.0);
+
+        for (camera, mut shake) in &mut cameras {
+            if camera.0 == *player {
+                shake.add_trauma(strength * IMPACT_TRAUMA_MAX);
+            }
+        }
+    }
+}
+
+fn add_absorb_trauma(
+    mut absorbed_events: EventReader<DoodadAbsorbed>,
+    mut cameras: Query<(&PlayerCamera, &mut CameraShake)>,
+    roots: Query<&PlayerId>,
+) {
+    let mut counts = HashMap::new();
+    for event in absorbed_events.iter() {
+        if let Ok(player) = roots.get(event.player) {
+            *counts.entry(*player).or_insert(0) += 1;
+        }
+    }
+
+    for (camera, mut shake) in &mut cameras {
+        let count = counts.get(&camera.0).copied().unwrap_or_default();
+        if count > ABSORB_COUNT_MIN {
+            shake.add_trauma((count - ABSORB_COUNT_MIN) as f32 * ABSORB_TRAUMA_PER_DOODAD);
+        }
+    }
+}
+
+fn shake_camera(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut cameras: Query<(&mut CameraShake, &mut Transform)>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (mut shake, mut transform) in &mut cameras {
+        // undo last frame's shake first
+        transform.translation -= shake.offset.extend(0.0);
+        transform.rotate(Quat::from_rotation_z(-shake.angle));
+
+        shake.trauma = (shake.trauma - TRAUMA_DECAY * time.delta_seconds()).max(0.0);
+
+        let intensity = shake.trauma.powi(2) * settings.screen_shake;
+        if intensity <= 0.0 {
+            shake.offset = Vec2::ZERO;
+            shake.angle = 0.0;
+            continue;
+        }
+
+        shake.offset = Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0))
+            * MAX_SHAKE_OFFSET
+            * intensity;
+        shake.angle = rng.gen_range(-1.0..1.0) * MAX_SHAKE_ANGLE * intensity;
+
+        transform.translation += shake.offset.extend(0.0);
+        transform.rotate(Quat::from_rotation_z(shake.angle));
+    }
+}
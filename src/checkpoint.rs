@@ -0,0 +1,248 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::*;
+use rand::seq::index;
+
+use crate::cluster::{Cluster, StuckTo};
+use crate::doodad::{DoodadKind, GameRng};
+use crate::loading::MeshAssets;
+use crate::player::{self, GodMode, Player, PlayerId};
+use crate::settings::{AttachmentMode, Settings};
+use crate::soft::SoftAttached;
+use crate::triggers::{CheckpointReached, KillPlaneEntered};
+use crate::GameState;
+
+pub struct CheckpointPlugin;
+
+/// This plugin snapshots a player's whole cluster whenever they reach a
+/// checkpoint, and restores it when they die, minus a penalty set by
+/// [`Settings::respawn_penalty`].
+impl Plugin for CheckpointPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(add_initial_checkpoints)
+                .with_system(save_checkpoint)
+                .with_system(restore_checkpoint),
+        );
+    }
+}
+
+/// Everything needed to rebuild a player's cluster as it was at their last
+/// checkpoint, kept on the root.
+#[derive(Component)]
+struct Checkpoint {
+    position: Vec2,
+    rotation: Quat,
+    pieces: Vec<PieceSnapshot>,
+}
+
+struct PieceSnapshot {
+    kind: DoodadKind,
+    /// Relative to the root of the cluster.
+    transform: Transform,
+    stuck_to_root: bool,
+    /// The other pieces it's stuck to, by their index in the checkpoint.
+    links: Vec<usize>,
+}
+
+/// Players start out with a checkpoint where they spawned.
+fn add_initial_checkpoints(
+    mut commands: Commands,
+    roots: Query<(Entity, &Transform), (With<Player>, Without<Parent>, Without<Checkpoint>)>,
+) {
+    for (root, transform) in &roots {
+        commands.entity(root).insert(Checkpoint {
+            position: transform.translation.truncate(),
+            rotation: transform.rotation,
+            pieces: Vec::new(),
+        });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn save_checkpoint(
+    mut checkpoint_events: EventReader<CheckpointReached>,
+    mut roots: Query<
+        (
+            &Transform,
+            &GlobalTransform,
+            &mut Checkpoint,
+            Option<&Cluster>,
+        ),
+        Without<Parent>,
+    >,
+    pieces: Query<(Entity, &Parent, &Transform, &DoodadKind), With<Player>>,
+    soft_pieces: Query<(&SoftAttached, &GlobalTransform, &DoodadKind)>,
+) {
+    for CheckpointReached {
+        trigger,
+        player: root,
+        position,
+    } in checkpoint_events.iter()
+    {
+        let (transform, global_transform, mut checkpoint, cluster) = match roots.get_mut(*root) {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+        let root_matrix = global_transform.compute_matrix();
+
+        let rigid: Vec<_> = pieces
+            .iter()
+            .filter(|(_, parent, _, _)| parent.get() == *root)
+            .collect();
+        let indices: HashMap<_, _> = rigid
+            .iter()
+            .enumerate()
+            .map(|(index, (piece, ..))| (*piece, index))
+            .collect();
+
+        *checkpoint = Checkpoint {
+            position: *position,
+            rotation: transform.rotation,
+            pieces: rigid
+                .iter()
+                .map(|(piece, _, transform, kind)| {
+                    let stuck_to = cluster
+                        .and_then(|cluster| cluster.links(*piece))
+                        .unwrap_or_else(StuckTo::root);
+                    PieceSnapshot {
+                        kind: **kind,
+                        transform: **transform,
+                        stuck_to_root: stuck_to.root,
+                        links: stuck_to
+                            .pieces
+                            .iter()
+                            .filter_map(|other| indices.get(other).copied())
+                            .collect(),
+                    }
+                })
+                // restored however pieces are attached by then
+                .chain(
+                    soft_pieces
+                        .iter()
+                        .filter(|(attached, _, _)| attached.root() == *root)
+                        .map(|(_, transform, kind)| PieceSnapshot {
+                            kind: *kind,
+                            transform: Transform::from_matrix(
+                                root_matrix.inverse() * transform.compute_matrix(),
+                            ),
+                            stuck_to_root: true,
+                            links: Vec::new(),
+                        }),
+                )
+                .collect(),
+        };
+
+        log::info!(
+            "player {root:?} saved checkpoint {trigger:?} with {} pieces",
+            checkpoint.pieces.len()
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn restore_checkpoint(
+    mut commands: Commands,
+    mut kill_plane_events: EventReader<KillPlaneEntered>,
+    god_mode: Res<GodMode>,
+    mut rng: ResMut<GameRng>,
+    settings: Res<Settings>,
+    meshes: Res<MeshAssets>,
+    mut roots: Query<
+        (
+            &Checkpoint,
+            &mut Transform,
+            &mut Velocity,
+            Option<&PlayerId>,
+        ),
+        (With<Player>, Without<Parent>),
+    >,
+    pieces: Query<(Entity, &Parent), With<Player>>,
+    soft_pieces: Query<(Entity, &SoftAttached)>,
+) {
+    // only respawn each player once, even if they hit several kill planes at once
+    let mut respawned = HashSet::new();
+
+    for KillPlaneEntered {
+        trigger,
+        player: root,
+    } in kill_plane_events.iter()
+    {
+        if !respawned.insert(*root) {
+            continue;
+        }
+
+        let (checkpoint, mut transform, mut velocity, id) = match roots.get_mut(*root) {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+
+        transform.translation = checkpoint.position.extend(transform.translation.z);
+        transform.rotation = checkpoint.rotation;
+        *velocity = Velocity::zero();
+
+        // Rebuild the cluster from scratch, since it may have gained or lost
+        // pieces since the checkpoint.
+        let rigid = pieces
+            .iter()
+            .filter(|(_, parent)| parent.get() == *root)
+            .map(|(piece, _)| piece);
+        let soft = soft_pieces
+            .iter()
+            .filter(|(_, attached)| attached.root() == *root)
+            .map(|(piece, _)| piece);
+        for piece in rigid.chain(soft) {
+            commands.entity(piece).despawn_recursive();
+        }
+
+        let penalty = if god_mode.0 && id.is_some() {
+            0.0
+        } else {
+            settings.respawn_penalty.clamp(0.0, 1.0)
+        };
+        let lost = (checkpoint.pieces.len() as f32 * penalty).round() as usize;
+        let kept = checkpoint.pieces.len() - lost;
+
+        // the root has no parent, so this is where it will be
+        let root_transform = GlobalTransform::from(*transform);
+        let restored: HashMap<_, _> = index::sample(&mut rng.0, checkpoint.pieces.len(), kept)
+            .into_iter()
+            .map(|index| {
+                let piece = &checkpoint.pieces[index];
+                let entity = player::spawn_attached_doodad(
+                    &mut commands,
+                    &meshes,
+                    settings.attachment_mode,
+                    *root,
+                    &root_transform,
+                    piece.kind,
+                    piece.transform,
+                );
+                (index, entity)
+            })
+            .collect();
+
+        // hold the restored pieces together the way they were
+        if settings.attachment_mode == AttachmentMode::Rigid {
+            for (index, entity) in &restored {
+                let piece = &checkpoint.pieces[*index];
+                let links: Vec<_> = piece
+                    .links
+                    .iter()
+                    .filter_map(|other| restored.get(other).copied())
+                    .collect();
+                commands.entity(*entity).insert(StuckTo {
+                    // what held it on was lost, so it holds onto the root instead
+                    root: piece.stuck_to_root || links.is_empty(),
+                    pieces: links,
+                });
+            }
+        }
+
+        log::info!(
+            "player {root:?} respawned at checkpoint after entering {trigger:?}, lost {lost} pieces"
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::math::{Isometry, Vector};
+use bevy_rapier2d::rapier::parry::query;
+use bevy_rapier2d::rapier::parry::shape::Shape;
+
+use crate::doodad::DoodadKind;
+use crate::physics::Impact;
+use crate::player::{self, Player};
+use crate::GameState;
+
+pub struct ClusterPlugin;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+struct ClusterStage;
+
+/// This plugin keeps track of how the pieces of the player hold together.
+/// Attached colliders are merged into a single compound collider on the root,
+/// so big clusters stay cheap to simulate and query, while the pieces are
+/// still drawn individually. Each piece remembers what it's stuck to, and
+/// anything that loses its connection to the root falls off.
+impl Plugin for ClusterPlugin {
+    fn build(&self, app: &mut App) {
+        // Runs right after the frame's attach and detach commands have been
+        // applied, but before Rapier picks up the changes for this step.
+        app.add_stage_after(CoreStage::Update, ClusterStage, SystemStage::parallel())
+            .add_system_to_stage(ClusterStage, update_cluster)
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(knock_off_pieces));
+    }
+}
+
+/// Impacts on a single piece at least this hard knock it off the player.
+const KNOCK_OFF_IMPULSE: f32 = 500.0;
+
+/// What a piece is stuck to as it joins a cluster, picked up by the cluster
+/// plugin along with its collider. Pieces without one, like those spawned in
+/// place, hold onto the root directly.
+#[derive(Component, Debug, Clone, Default)]
+pub struct StuckTo {
+    pub root: bool,
+    /// Other pieces of the same cluster.
+    pub pieces: Vec<Entity>,
+}
+
+impl StuckTo {
+    pub fn root() -> Self {
+        Self {
+            root: true,
+            pieces: Vec::new(),
+        }
+    }
+}
+
+/// The pieces attached to the player and how they hold together, kept on the root.
+#[derive(Component)]
+pub struct Cluster {
+    /// The root's own collider.
+    base: Collider,
+    pieces: HashMap<Entity, Piece>,
+    /// Which piece each shape of the current compound collider belongs to,
+    /// after the root's own shape.
+    shape_order: Vec<Entity>,
+}
+
+struct Piece {
+    /// The piece's collider, relative to the root.
+    position: Vect,
+    rotation: Rot,
+    collider: Collider,
+    stuck_to_root: bool,
+    /// Other pieces this one is stuck to, in both directions.
+    links: HashSet<Entity>,
+}
+
+impl Cluster {
+    pub fn new(base: Collider) -> Self {
+        Self {
+            base,
+            pieces: HashMap::new(),
+            shape_order: Vec::new(),
+        }
+    }
+
+    /// How many pieces are attached, not counting the root.
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    /// What a shape touching the cluster is stuck to, going by which shapes
+    /// of the root's compound collider it overlaps.
+    pub fn stuck_to(
+        &self,
+        collider: &Collider,
+        transform: &GlobalTransform,
+        other: &Collider,
+        other_transform: &GlobalTransform,
+    ) -> StuckTo {
+        let position = isometry(transform);
+        let other_position = isometry(other_transform);
+        let touches = |shape_position: &Isometry, shape: &dyn Shape| {
+            query::intersection_test(shape_position, shape, &other_position, &*other.raw)
+                .unwrap_or(false)
+        };
+
+        let mut stuck_to = StuckTo::default();
+        match collider.raw.as_compound() {
+            Some(compound) => {
+                for (index, (shape_position, shape)) in compound.shapes().iter().enumerate() {
+                    if !touches(&(position * shape_position), &**shape) {
+                        continue;
+                    }
+                    match self.piece_for_shape(index as u32) {
+                        Some(piece) => stuck_to.pieces.push(piece),
+                        None => stuck_to.root = true,
+                    }
+                }
+            }
+            // a bare root
+            None => stuck_to.root = touches(&position, &*collider.raw),
+        }
+
+        // It was found touching the cluster, so this only guards against rounding.
+        if !stuck_to.root && stuck_to.pieces.is_empty() {
+            stuck_to.root = true;
+        }
+        stuck_to
+    }
+
+    /// What a piece in the cluster is stuck to, in both directions.
+    pub fn links(&self, entity: Entity) -> Option<StuckTo> {
+        let piece = self.pieces.get(&entity)?;
+        Some(StuckTo {
+            root: piece.stuck_to_root,
+            pieces: piece.links.iter().copied().collect(),
+        })
+    }
+
+    fn add(
+        &mut self,
+        entity: Entity,
+        transform: &Transform,
+        collider: &Collider,
+        stuck_to: &StuckTo,
+    ) {
+        let mut shape = collider.clone();
+        // the piece's transform is relative to the root, whose own scale is
+        // applied to the whole compound by Rapier
+        shape.set_scale(transform.scale.truncate(), 10);
+        let (rotation, _, _) = transform.rotation.to_euler(EulerRot::ZYX);
+        let position = transform.translation.truncate();
+
+        // pieces that joined first can already be stuck to this one
+        let mut links: HashSet<_> = stuck_to.pieces.iter().copied().collect();
+        links.extend(
+            self.pieces
+                .iter()
+                .filter(|(_, other)| other.links.contains(&entity))
+                .map(|(other, _)| *other),
+        );
+        links.remove(&entity);
+
+        for other in &links {
+            if let Some(other) = self.pieces.get_mut(other) {
+                other.links.insert(entity);
+            }
+        }
+
+        self.pieces.insert(
+            entity,
+            Piece {
+                position,
+                rotation,
+                collider: shape,
+                stuck_to_root: stuck_to.root,
+                links,
+            },
+        );
+    }
+
+    fn remove(&mut self, entity: Entity) -> bool {
+        let piece = match self.pieces.remove(&entity) {
+            Some(piece) => piece,
+            None => return false,
+        };
+
+        for other in &piece.links {
+            if let Some(other) = self.pieces.get_mut(other) {
+                other.links.remove(&entity);
+            }
+        }
+        true
+    }
+
+    /// Pieces that are no longer connected to the root, not even through other pieces.
+    fn disconnected(&self) -> Vec<Entity> {
+        let mut connected = HashSet::new();
+        let mut to_visit: Vec<_> = self
+            .pieces
+            .iter()
+            .filter(|(_, piece)| piece.stuck_to_root)
+            .map(|(entity, _)| *entity)
+            .collect();
+
+        while let Some(entity) = to_visit.pop() {
+            // links can point at pieces that haven't joined yet, or never will
+            let piece = match self.pieces.get(&entity) {
+                Some(piece) => piece,
+                None => continue,
+            };
+            if connected.insert(entity) {
+                to_visit.extend(piece.links.iter().copied());
+            }
+        }
+
+        self.pieces
+            .keys()
+            .filter(|entity| !connected.contains(entity))
+            .copied()
+            .collect()
+    }
+
+    fn rebuild_collider(&mut self) -> Collider {
+        self.shape_order = self.pieces.keys().copied().collect();
+
+        if self.pieces.is_empty() {
+            return self.base.clone();
+        }
+
+        let pieces = &self.pieces;
+        Collider::compound(
+            std::iter::once((Vect::ZERO, 0.0, self.base.clone()))
+                .chain(self.shape_order.iter().map(|entity| {
+                    let piece = &pieces[entity];
+                    (piece.position, piece.rotation, piece.collider.clone())
+                }))
+                .collect(),
+        )
+    }
+
+    /// The piece a shape of the compound collider belongs to, or `None` for the root itself.
+    fn piece_for_shape(&self, shape: u32) -> Option<Entity> {
+        let index = (shape as usize).checked_sub(1)?;
+        self.shape_order.get(index).copied()
+    }
+}
+
+/// Where a transform puts shapes, for Rapier's own geometry queries.
+fn isometry(transform: &GlobalTransform) -> Isometry {
+    let (_, rotation, translation) = transform.to_scale_rotation_translation();
+    let (angle, _, _) = rotation.to_euler(EulerRot::ZYX);
+    Isometry::new(Vector::new(translation.x, translation.y), angle)
+}
+
+#[allow(clippy::type_complexity)]
+fn update_cluster(
+    mut commands: Commands,
+    removed: RemovedComponents<Player>,
+    mut roots: Query<
+        (
+            Entity,
+            &mut Cluster,
+            &mut Collider,
+            &GlobalTransform,
+            &Velocity,
+        ),
+        (With<Player>, Without<Parent>),
+    >,
+    attached: Query<
+        (Entity, &Parent, &Transform, &Collider, Option<&StuckTo>),
+        (With<Player>, With<Parent>),
+    >,
+    moved: Query<(Entity, &Parent), (With<Player>, Changed<Parent>)>,
+    pieces: Query<(&GlobalTransform, &DoodadKind), (With<Player>, With<Parent>)>,
+) {
+    // we don't know which player these belonged to anymore
+    let removed: Vec<_> = removed.iter().collect();
+
+    for (root, mut cluster, mut collider, root_transform, root_velocity) in &mut roots {
+        let mut changed = false;
+        let mut lost_pieces = false;
+
+        for piece in &removed {
+            if cluster.remove(*piece) {
+                changed = true;
+                lost_pieces = true;
+            }
+        }
+
+        // pieces taken over by another cluster
+        for (piece, parent) in &moved {
+            if parent.get() != root && cluster.remove(piece) {
+                changed = true;
+                lost_pieces = true;
+            }
+        }
+
+        // pieces still having their own collider have just been attached
+        for (piece, parent, transform, piece_collider, stuck_to) in &attached {
+            if parent.get() == root {
+                let stuck_to = stuck_to.cloned().unwrap_or_else(StuckTo::root);
+                cluster.add(piece, transform, piece_collider, &stuck_to);
+                commands
+                    .entity(piece)
+                    .remove::<Collider>()
+                    .remove::<StuckTo>();
+                changed = true;
+            }
+        }
+
+        if lost_pieces {
+            let disconnected = cluster.disconnected();
+            if !disconnected.is_empty() {
+                log::info!("{} pieces broke off player {root:?}", disconnected.len());
+            }
+
+            let center = root_transform.translation().truncate();
+            for piece in disconnected {
+                cluster.remove(piece);
+
+                if let Ok((transform, kind)) = pieces.get(piece) {
+                    // keep moving along with the spinning cluster
+                    let offset = transform.translation().truncate() - center;
+                    let velocity = Velocity::linear(
+                        root_velocity.linvel + offset.perp() * root_velocity.angvel,
+                    );
+                    player::release_doodad(&mut commands, root, piece, *kind, transform, velocity);
+                }
+            }
+        }
+
+        if changed {
+            *collider = cluster.rebuild_collider();
+        }
+    }
+}
+
+/// Hard hits knock off the piece that took them, along with everything that
+/// was only held on by it.
+fn knock_off_pieces(
+    mut commands: Commands,
+    mut impacts: EventReader<Impact>,
+    rapier_context: Res<RapierContext>,
+    roots: Query<(Entity, &Cluster, &Velocity), (With<Player>, Without<Parent>)>,
+    pieces: Query<(&GlobalTransform, &DoodadKind), (With<Player>, With<Parent>)>,
+) {
+    let mut knocked_off = HashSet::new();
+
+    for impact in impacts.iter() {
+        if impact.impulse < KNOCK_OFF_IMPULSE {
+            continue;
+        }
+
+        let [a, b] = impact.colliders;
+        let (root, cluster, root_velocity) = match roots.get(a).or_else(|_| roots.get(b)) {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+
+        let pair = match rapier_context.contact_pair(a, b) {
+            Some(pair) => pair,
+            None => continue,
+        };
+
+        // The contact tells which shape of the compound collider was hit,
+        // which was built just before the step that produced it.
+        let piece = pair
+            .manifolds()
+            .find(|manifold| manifold.num_points() > 0)
+            .and_then(|manifold| {
+                let shape = if pair.collider1() == root {
+                    manifold.subshape1()
+                } else {
+                    manifold.subshape2()
+                };
+                cluster.piece_for_shape(shape)
+            });
+
+        let piece = match piece {
+            Some(piece) if knocked_off.insert(piece) => piece,
+            _ => continue,
+        };
+
+        if let Ok((transform, kind)) = pieces.get(piece) {
+            let velocity = Velocity::linear(root_velocity.linvel);
+            player::release_doodad(&mut commands, root, piece, *kind, transform, velocity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cluster_with(pieces: &[(Entity, StuckTo)]) -> Cluster {
+        let mut cluster = Cluster::new(Collider::ball(0.5));
+        for (entity, stuck_to) in pieces {
+            cluster.add(
+                *entity,
+                &Transform::default(),
+                &Collider::ball(0.5),
+                stuck_to,
+            );
+        }
+        cluster
+    }
+
+    fn stuck_to(pieces: &[Entity]) -> StuckTo {
+        StuckTo {
+            root: false,
+            pieces: pieces.to_vec(),
+        }
+    }
+
+    #[test]
+    fn chain_falls_off_past_removed_piece() {
+        let [a, b] = [Entity::from_raw(1), Entity::from_raw(2)];
+        let mut cluster = cluster_with(&[(a, StuckTo::root()), (b, stuck_to(&[a]))]);
+
+        assert!(cluster.disconnected().is_empty());
+        assert!(cluster.remove(a));
+        assert_eq!(cluster.disconnected(), vec![b]);
+    }
+
+    #[test]
+    fn piece_with_another_path_stays() {
+        let [a, b, c] = [
+            Entity::from_raw(1),
+            Entity::from_raw(2),
+            Entity::from_raw(3),
+        ];
+        let mut cluster = cluster_with(&[
+            (a, StuckTo::root()),
+            (b, StuckTo::root()),
+            (c, stuck_to(&[a, b])),
+        ]);
+
+        assert!(cluster.remove(a));
+        assert!(cluster.disconnected().is_empty());
+    }
+
+    #[test]
+    fn links_work_in_either_order() {
+        let [a, b] = [Entity::from_raw(1), Entity::from_raw(2)];
+        // b joins first, stuck to a which joins later in the same frame
+        let mut cluster = cluster_with(&[(b, stuck_to(&[a])), (a, StuckTo::root())]);
+
+        assert!(cluster.disconnected().is_empty());
+        assert!(cluster.remove(a));
+        assert_eq!(cluster.disconnected(), vec![b]);
+    }
+}
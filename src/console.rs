@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+
+use bevy::ecs::system::CommandQueue;
+use bevy::input::InputSystem;
+use bevy::window::ReceivedCharacter;
+use bevy::{log, prelude::*};
+
+use crate::loading::FontAssets;
+use crate::GameState;
+
+pub struct ConsolePlugin;
+
+/// This plugin adds a developer console, only in dev builds. Press ` to open
+/// it and type a command, like `help` to list every command there is. Any
+/// plugin can add its own commands with [`ConsoleAppExt::add_console_command`].
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Console>()
+            .add_console_command("help", "help", help)
+            .add_console_command("state", "state <menu|playing|editor>", set_state)
+            .add_system_set(SystemSet::on_exit(GameState::Loading).with_system(spawn_console))
+            .add_system_to_stage(CoreStage::PreUpdate, read_console_input.after(InputSystem))
+            .add_system(run_console_commands.exclusive_system())
+            .add_system(update_console_text);
+    }
+}
+
+const TOGGLE_KEY: KeyCode = KeyCode::Grave;
+/// How many lines of output stay on screen.
+const MAX_LINES: usize = 12;
+const FONT_SIZE: f32 = 18.0;
+
+/// Runs a command with the words typed after its name, returning what to
+/// print back.
+pub type CommandHandler = fn(&mut World, &[&str]) -> Result<String, String>;
+
+struct ConsoleCommand {
+    name: &'static str,
+    usage: &'static str,
+    handler: CommandHandler,
+}
+
+/// Every command the console knows about.
+#[derive(Default)]
+struct ConsoleCommands(Vec<ConsoleCommand>);
+
+pub trait ConsoleAppExt {
+    /// Add a command to the developer console, replacing any other command
+    /// with the same name. `usage` shows how it is typed, for `help`.
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        handler: CommandHandler,
+    ) -> &mut Self;
+}
+
+impl ConsoleAppExt for App {
+    fn add_console_command(
+        &mut self,
+        name: &'static str,
+        usage: &'static str,
+        handler: CommandHandler,
+    ) -> &mut Self {
+        // plugins may be added before or after the console itself
+        let mut commands = self
+            .world
+            .get_resource_or_insert_with(ConsoleCommands::default);
+        commands.0.retain(|command| command.name != name);
+        commands.0.push(ConsoleCommand {
+            name,
+            usage,
+            handler,
+        });
+        self
+    }
+}
+
+/// Run `f` with [`Commands`] for the world, applying them afterwards. Handy
+/// for reusing spawning helpers from command handlers.
+pub fn with_commands<R>(world: &mut World, f: impl FnOnce(&World, &mut Commands) -> R) -> R {
+    let mut queue = CommandQueue::default();
+    let result = f(world, &mut Commands::new(&mut queue, world));
+    queue.apply(world);
+    result
+}
+
+/// Parse the argument at `index`, with a readable error if it's missing or invalid.
+pub fn parse_arg<T: std::str::FromStr>(
+    args: &[&str],
+    index: usize,
+    name: &str,
+) -> Result<T, String> {
+    let arg = args.get(index).ok_or(format!("missing {name}"))?;
+    arg.parse().map_err(|_| format!("invalid {name}: {arg}"))
+}
+
+/// Fail unless the game is in `state`, for commands that only make sense there.
+pub fn require_state(world: &World, state: GameState) -> Result<(), String> {
+    let current = world.resource::<State<GameState>>().current();
+    if *current == state {
+        Ok(())
+    } else {
+        Err(format!("only works in {state:?}, not {current:?}"))
+    }
+}
+
+#[derive(Default)]
+struct Console {
+    open: bool,
+    input: String,
+    /// Lines entered but not run yet.
+    pending: Vec<String>,
+    output: VecDeque<String>,
+    /// Lines entered before, to bring back with the arrow keys.
+    history: Vec<String>,
+    /// How far back in the history the arrow keys went.
+    history_index: Option<usize>,
+}
+
+impl Console {
+    fn print(&mut self, line: impl Into<String>) {
+        self.output.push_back(line.into());
+        while self.output.len() > MAX_LINES {
+            self.output.pop_front();
+        }
+    }
+}
+
+#[derive(Component)]
+struct ConsoleUi;
+
+#[derive(Component)]
+struct ConsoleText;
+
+fn spawn_console(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    bottom: Val::Px(0.0),
+                    ..default()
+                },
+                padding: UiRect::all(Val::Px(8.0)),
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(ConsoleUi)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::from_section(
+                        "",
+                        TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: FONT_SIZE,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    ),
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(ConsoleUi)
+                .insert(ConsoleText);
+        });
+}
+
+/// Type into the console while it's open, keeping the keys from reaching the
+/// rest of the game.
+fn read_console_input(
+    mut console: ResMut<Console>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut ui: Query<&mut Visibility, With<ConsoleUi>>,
+) {
+    // read every frame, so nothing typed while closed shows up later
+    let typed: Vec<_> = characters.iter().map(|event| event.char).collect();
+
+    if keyboard_input.just_pressed(TOGGLE_KEY) {
+        console.open = !console.open;
+        for mut visibility in &mut ui {
+            visibility.is_visible = console.open;
+        }
+        keyboard_input.reset(TOGGLE_KEY);
+    }
+
+    if !console.open {
+        return;
+    }
+
+    for char in typed {
+        if !char.is_control() && char != '`' {
+            console.input.push(char);
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        console.input.pop();
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Up) || keyboard_input.just_pressed(KeyCode::Down) {
+        let index = match (
+            console.history_index,
+            keyboard_input.just_pressed(KeyCode::Up),
+        ) {
+            (None, true) => console.history.len().checked_sub(1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) => Some(index + 1).filter(|index| *index < console.history.len()),
+        };
+        console.history_index = index;
+        console.input = index.map_or_else(String::new, |index| console.history[index].clone());
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let line = std::mem::take(&mut console.input);
+        if !line.trim().is_empty() {
+            console.history.push(line.clone());
+            console.pending.push(line);
+        }
+        console.history_index = None;
+    }
+
+    let pressed: Vec<_> = keyboard_input.get_pressed().copied().collect();
+    for key in pressed {
+        keyboard_input.reset(key);
+    }
+}
+
+fn run_console_commands(world: &mut World) {
+    let pending = std::mem::take(&mut world.resource_mut::<Console>().pending);
+
+    for line in pending {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => continue,
+        };
+
+        let handler = world
+            .resource::<ConsoleCommands>()
+            .0
+            .iter()
+            .find(|command| command.name == name)
+            .map(|command| command.handler);
+
+        let result = match handler {
+            Some(handler) => handler(world, args),
+            None => Err(format!("unknown command {name}, try help")),
+        };
+
+        let mut console = world.resource_mut::<Console>();
+        console.print(format!("> {line}"));
+        match result {
+            Ok(output) => {
+                log::info!("console: {line}: {output}");
+                if !output.is_empty() {
+                    console.print(output);
+                }
+            }
+            Err(err) => {
+                log::warn!("console: {line}: {err}");
+                console.print(format!("error: {err}"));
+            }
+        }
+    }
+}
+
+fn update_console_text(console: Res<Console>, mut texts: Query<&mut Text, With<ConsoleText>>) {
+    if !console.is_changed() {
+        return;
+    }
+
+    let mut contents = console
+        .output
+        .iter()
+        .fold(String::new(), |mut contents, line| {
+            contents.push_str(line);
+            contents.push('\n');
+            contents
+        });
+    contents.push_str("> ");
+    contents.push_str(&console.input);
+
+    for mut text in &mut texts {
+        text.sections[0].value = contents.clone();
+    }
+}
+
+fn help(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let mut usages: Vec<_> = world
+        .resource::<ConsoleCommands>()
+        .0
+        .iter()
+        .map(|command| command.usage)
+        .collect();
+    usages.sort_unstable();
+    Ok(usages.join("\n"))
+}
+
+fn set_state(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let name = parse_arg::<String>(args, 0, "state")?;
+    let state = match name.to_lowercase().as_str() {
+        "menu" => GameState::Menu,
+        "playing" => GameState::Playing,
+        "editor" => GameState::Editor,
+        _ => return Err(format!("no state called {name}")),
+    };
+
+    world
+        .resource_mut::<State<GameState>>()
+        .set(state.clone())
+        .map_err(|err| format!("{err:?}"))?;
+    Ok(format!("switching to {state:?}"))
+}
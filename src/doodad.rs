@@ -1,10 +1,21 @@
 use std::time::Duration;
 
+use bevy::ecs::system::EntityCommands;
 use bevy::{log, prelude::*};
 use bevy_rapier2d::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "dev")]
+use crate::console::{self, ConsoleAppExt};
+use crate::level::{CurrentLevel, Level};
 use crate::loading::MeshAssets;
+use crate::net::{self, NetRole};
 use crate::physics;
+use crate::player::{OriginalMaterial, PlayerId};
+use crate::settings::Settings;
 use crate::GameState;
 
 pub struct DoodadPlugin;
@@ -12,25 +23,126 @@ pub struct DoodadPlugin;
 impl Plugin for DoodadPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(SpawnTimer(Timer::new(Duration::from_secs(1), true)))
-            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(spawn_doodads));
+            .init_resource::<GameRng>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(compute_level_bounds)
+                    .with_system(spawn_placed_doodads),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn_doodads)
+                    .with_system(recycle_doodads),
+            );
+
+        #[cfg(feature = "dev")]
+        app.add_console_command("spawn", "spawn <square|ball|plank> <x> <y>", spawn_command)
+            .add_console_command("seed", "seed <number>", seed_command);
     }
 }
 
+/// How far outside the level geometry doodads can go before they're recycled.
+const LEVEL_BOUNDS_MARGIN: f32 = 1000.0;
+/// Doodads at least this far from every player count as off-screen.
+const OFF_SCREEN_DISTANCE: f32 = 1500.0;
+/// How long doodads can stay off-screen before they're recycled, in seconds.
+const OFF_SCREEN_TIMEOUT: f32 = 10.0;
+
 struct SpawnTimer(Timer);
 
+/// Randomness that changes how the game plays out, like which doodads spawn,
+/// seeded so that a run can be played out the same way again.
+pub struct GameRng(pub StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        let seed = rand::random();
+        log::info!("random seed is {seed}");
+        Self(StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Anything outside of these is recycled right away.
+struct LevelBounds {
+    min: Vec2,
+    max: Vec2,
+}
+
 #[derive(Component)]
 pub struct Doodad;
 
+/// A recycled doodad, hidden and out of the physics world until it is spawned again.
+#[derive(Component)]
+struct Pooled;
+
+/// How long a doodad has been far away from the camera, in seconds.
+#[derive(Component, Default)]
+struct OffScreen(f32);
+
+/// The different kinds of doodad that can be spawned. Each kind has its own
+/// texture, and its collider is defined here in unit size and then scaled up.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DoodadKind {
+    Square,
+    Ball,
+    Plank,
+}
+
+impl DoodadKind {
+    pub const ALL: [DoodadKind; 3] = [DoodadKind::Square, DoodadKind::Ball, DoodadKind::Plank];
+
+    pub fn collider(self) -> Collider {
+        match self {
+            DoodadKind::Square => Collider::round_cuboid(0.5, 0.5, 0.05),
+            DoodadKind::Ball => Collider::ball(0.5),
+            DoodadKind::Plank => Collider::cuboid(0.5, 0.5),
+        }
+    }
+
+    /// The size of the doodad in the world, used as its transform scale.
+    pub fn size(self) -> Vec2 {
+        match self {
+            DoodadKind::Square | DoodadKind::Ball => Vec2::splat(20.0),
+            DoodadKind::Plank => Vec2::new(40.0, 10.0),
+        }
+    }
+}
+
+/// A doodad placed in the level, waiting to be picked up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoodadPlacement {
+    pub kind: DoodadKind,
+    pub position: Vec2,
+    /// Rotation in radians, counter-clockwise.
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn spawn_doodads(
     mut commands: Commands,
     mut spawn_timer: ResMut<SpawnTimer>,
     time: Res<Time>,
+    settings: Res<Settings>,
+    mut rng: ResMut<GameRng>,
+    net_role: Option<Res<NetRole>>,
     assets: Res<MeshAssets>,
     rapier_context: Res<RapierContext>,
     doodads: Query<Entity, With<Doodad>>,
+    pooled: Query<Entity, With<Pooled>>,
 ) {
+    // clients only show the doodads the host sends them
+    if net::is_client(&net_role) {
+        return;
+    }
+
     if spawn_timer.0.tick(time.delta()).just_finished() {
-        let collider = assets.square.collider.clone();
+        let kind = *DoodadKind::ALL.choose(&mut rng.0).unwrap();
+        let asset = assets.doodad(kind);
+
+        // the spawned collider gets scaled by its transform, so check with the same size
+        let mut collider = asset.collider.clone();
+        collider.set_scale(kind.size(), 1);
         let shape_pos = Vec2::new(100.0, -20.0);
         let filter = QueryFilter::default();
 
@@ -49,14 +161,192 @@ fn spawn_doodads(
             return;
         }
 
-        commands
-            .spawn_bundle(
-                physics::ColliderBundle::from(&assets.square).with_transform(
-                    Transform::from_translation(shape_pos.extend(50.0))
-                        .with_scale(Vec3::splat(20.0)),
-                ),
+        // Released pieces turn back into free doodads, which can take the
+        // total past the cap, so shrink the pool to make up for it.
+        let free = doodads.iter().count();
+        let mut pool: Vec<_> = pooled.iter().collect();
+        let excess = (free + pool.len()).saturating_sub(settings.doodad_cap);
+        for entity in pool.drain(..excess.min(pool.len())) {
+            commands.entity(entity).despawn();
+        }
+
+        if free >= settings.doodad_cap {
+            return;
+        }
+
+        // reuse a recycled doodad if there is one
+        let mut doodad = match pool.first() {
+            Some(entity) => {
+                let mut doodad = commands.entity(*entity);
+                doodad.remove::<Pooled>();
+                doodad
+            }
+            None => commands.spawn(),
+        };
+
+        init_doodad(&mut doodad, &assets, kind, shape_pos);
+    }
+}
+
+/// Turn an entity into a free doodad of the given kind at `position`.
+pub fn init_doodad(
+    doodad: &mut EntityCommands,
+    meshes: &MeshAssets,
+    kind: DoodadKind,
+    position: Vec2,
+) {
+    doodad
+        .insert_bundle(
+            physics::ColliderBundle::from(meshes.doodad(kind)).with_transform(
+                Transform::from_translation(position.extend(50.0))
+                    .with_scale(kind.size().extend(1.0)),
+            ),
+        )
+        .insert(physics::CollideGroups::doodad())
+        .insert(Doodad)
+        .insert(OffScreen::default())
+        .insert(kind);
+}
+
+fn spawn_placed_doodads(
+    mut commands: Commands,
+    net_role: Option<Res<NetRole>>,
+    assets: Res<MeshAssets>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        // clients only show the doodads the host sends them
+        Some(level) if !net::is_client(&net_role) => level,
+        _ => return,
+    };
+
+    for placement in &level.doodads {
+        let mut doodad = commands.spawn();
+        init_doodad(&mut doodad, &assets, placement.kind, placement.position);
+
+        doodad.insert(
+            Transform::from_translation(placement.position.extend(50.0))
+                .with_rotation(Quat::from_rotation_z(placement.rotation))
+                .with_scale(placement.kind.size().extend(1.0)),
+        );
+    }
+}
+
+fn compute_level_bounds(
+    mut commands: Commands,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        Some(level) => level,
+        None => return,
+    };
+
+    let (min, max) = level
+        .floors
+        .iter()
+        .fold((level.spawn, level.spawn), |(min, max), floor| {
+            // rough, but enough to contain rotated floors too
+            let extent = Vec2::splat(floor.size.max_element() / 2.0);
+            (
+                min.min(floor.position - extent),
+                max.max(floor.position + extent),
             )
-            .insert(physics::CollideGroups::doodad())
-            .insert(Doodad);
+        });
+
+    commands.insert_resource(LevelBounds {
+        min: min - LEVEL_BOUNDS_MARGIN,
+        max: max + LEVEL_BOUNDS_MARGIN,
+    });
+}
+
+/// Doodads that fall out of the level, or are left far behind for too long,
+/// are put back in the pool to be spawned again.
+fn recycle_doodads(
+    mut commands: Commands,
+    time: Res<Time>,
+    bounds: Option<Res<LevelBounds>>,
+    players: Query<&GlobalTransform, (With<PlayerId>, Without<Parent>)>,
+    mut doodads: Query<(Entity, &GlobalTransform, Option<&mut OffScreen>), With<Doodad>>,
+) {
+    // Measured from the players rather than the cameras, since the host has
+    // no cameras for players online. Anything near one of them stays.
+    let players: Vec<_> = players
+        .iter()
+        .map(|player| player.translation().truncate())
+        .collect();
+    if players.is_empty() {
+        return;
+    }
+
+    for (entity, transform, off_screen) in &mut doodads {
+        let position = transform.translation().truncate();
+
+        let out_of_bounds = bounds.as_ref().map_or(false, |bounds| {
+            position.cmplt(bounds.min).any() || position.cmpgt(bounds.max).any()
+        });
+
+        // released pieces of the player don't have a timer yet
+        let mut off_screen = match off_screen {
+            Some(off_screen) => off_screen,
+            None => {
+                commands.entity(entity).insert(OffScreen::default());
+                continue;
+            }
+        };
+
+        let distance = players
+            .iter()
+            .map(|player| position.distance(*player))
+            .fold(f32::INFINITY, f32::min);
+
+        if distance > OFF_SCREEN_DISTANCE {
+            off_screen.0 += time.delta_seconds();
+        } else {
+            off_screen.0 = 0.0;
+        }
+
+        if out_of_bounds || off_screen.0 > OFF_SCREEN_TIMEOUT {
+            commands
+                .entity(entity)
+                .remove::<Doodad>()
+                .remove::<RigidBody>()
+                .remove::<Collider>()
+                .remove::<OriginalMaterial>()
+                .insert(Visibility { is_visible: false })
+                .insert(Pooled);
+        }
     }
 }
+
+#[cfg(feature = "dev")]
+fn spawn_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    console::require_state(world, GameState::Playing)?;
+    let name: String = console::parse_arg(args, 0, "kind")?;
+    let kind = DoodadKind::ALL
+        .into_iter()
+        .find(|kind| format!("{kind:?}").eq_ignore_ascii_case(&name))
+        .ok_or(format!("no doodad called {name}"))?;
+    let position = Vec2::new(
+        console::parse_arg(args, 1, "x")?,
+        console::parse_arg(args, 2, "y")?,
+    );
+
+    console::with_commands(world, |world, commands| {
+        init_doodad(
+            &mut commands.spawn(),
+            world.resource::<MeshAssets>(),
+            kind,
+            position,
+        );
+    });
+    Ok(format!("spawned a {kind:?} at {position}"))
+}
+
+#[cfg(feature = "dev")]
+fn seed_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let seed: u64 = console::parse_arg(args, 0, "seed")?;
+    world.insert_resource(GameRng(StdRng::seed_from_u64(seed)));
+    Ok(format!("seeded with {seed}"))
+}
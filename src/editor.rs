@@ -0,0 +1,851 @@
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::mem;
+
+use bevy::asset::AssetServerSettings;
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::asset::FileAssetIo;
+use bevy::input::mouse::MouseWheel;
+use bevy::{log, prelude::*};
+
+use crate::camera::PlayerCamera;
+use crate::doodad::{DoodadKind, DoodadPlacement};
+use crate::level::{CurrentLevel, Floor, Level, LEVEL_Z};
+use crate::loading::{FontAssets, MeshAssets};
+use crate::powerups::{PickupSpawn, PowerUp, PICKUP_SIZE};
+use crate::settings::Settings;
+use crate::triggers::{TriggerKind, TriggerZone};
+use crate::GameState;
+
+pub struct EditorPlugin;
+
+/// This plugin is an in-game level editor, only in dev builds. Press F1 in
+/// the menu or while playing to open it, and F5 to play the edited level
+/// right away. Floors, triggers, spawn points, pickups and placed doodads
+/// are moved with the mouse and keyboard, and saved back to the `.level`
+/// file they were loaded from. Floors can also be resized and rotated, but
+/// triggers can only be resized and doodads only rotated, since the level
+/// format doesn't store a rotation for triggers or a size for doodads.
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(open_editor)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(remember_persistent_entities),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(clear_played_level))
+            .add_system_set(SystemSet::on_enter(GameState::Editor).with_system(setup_editor))
+            .add_system_set(
+                SystemSet::on_update(GameState::Editor)
+                    .with_system(move_camera)
+                    .with_system(edit_with_mouse)
+                    .with_system(edit_with_keys.after(edit_with_mouse))
+                    .with_system(update_stand_ins.after(edit_with_keys))
+                    .with_system(update_status.after(edit_with_keys))
+                    .with_system(play_level.after(edit_with_keys)),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(cleanup_editor));
+    }
+}
+
+/// Objects snap to a grid this many pixels wide, while snapping is on.
+const GRID_SIZE: f32 = 10.0;
+const ROTATION_STEP: f32 = PI / 12.0;
+const FINE_ROTATION_STEP: f32 = PI / 180.0;
+/// How many edits can be undone.
+const MAX_UNDO: usize = 100;
+const ZOOM_RATE: f32 = 1.1;
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 8.0;
+
+/// The size of the player and rival spawn markers, the same as a bare root.
+const SPAWN_SIZE: f32 = 30.0;
+/// Kill planes aren't drawn in the game, but need to be seen here.
+const KILL_PLANE_COLOR: Color = Color::rgba(0.5, 0.1, 0.5, 0.3);
+
+const HELP: &str = "F5: play  Ctrl+S: save  Ctrl+Z / Ctrl+Y: undo / redo\n\
+    Click: select  Drag: move  Shift+drag: resize  Q / E: rotate\n\
+    1: floor  2: trigger  3: pickup  4: rival  5: doodad\n\
+    Tab: change kind  Delete: remove  G: grid  Right drag: pan  Wheel: zoom";
+
+/// Entities that existed before play started, which are kept when going
+/// back to the editor. Everything else was spawned for the level.
+struct PersistentEntities(HashSet<Entity>);
+
+/// The level being edited, and its history.
+struct EditorLevel {
+    level: Level,
+    undo: Vec<Level>,
+    redo: Vec<Level>,
+    selected: Option<Object>,
+    snap: bool,
+    /// Bumped whenever objects are added, removed or change kind, so their
+    /// stand-ins are spawned again.
+    revision: u32,
+    /// The outcome of the last save, if any.
+    message: String,
+}
+
+impl EditorLevel {
+    fn new(level: Level) -> Self {
+        Self {
+            level,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            selected: None,
+            snap: true,
+            revision: 0,
+            message: String::new(),
+        }
+    }
+
+    /// Remember the level as it is now, before changing it.
+    fn checkpoint(&mut self) {
+        self.push_undo(self.level.clone());
+    }
+
+    fn push_undo(&mut self, level: Level) {
+        self.undo.push(level);
+        if self.undo.len() > MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    fn undo(&mut self) {
+        if let Some(level) = self.undo.pop() {
+            self.redo.push(mem::replace(&mut self.level, level));
+            self.reload();
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(level) = self.redo.pop() {
+            self.undo.push(mem::replace(&mut self.level, level));
+            self.reload();
+        }
+    }
+
+    fn reload(&mut self) {
+        self.selected = None;
+        self.revision += 1;
+    }
+
+    fn snap(&self, position: Vec2) -> Vec2 {
+        if self.snap {
+            (position / GRID_SIZE).round() * GRID_SIZE
+        } else {
+            position
+        }
+    }
+}
+
+/// Something in the level that can be edited, by its index in the level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Object {
+    Spawn,
+    Floor(usize),
+    Trigger(usize),
+    Pickup(usize),
+    Rival(usize),
+    Doodad(usize),
+}
+
+impl Object {
+    fn all(level: &Level) -> Vec<Object> {
+        std::iter::once(Object::Spawn)
+            .chain((0..level.floors.len()).map(Object::Floor))
+            .chain((0..level.triggers.len()).map(Object::Trigger))
+            .chain((0..level.pickups.len()).map(Object::Pickup))
+            .chain((0..level.rivals.len()).map(Object::Rival))
+            .chain((0..level.doodads.len()).map(Object::Doodad))
+            .collect()
+    }
+
+    fn position(self, level: &mut Level) -> &mut Vec2 {
+        match self {
+            Object::Spawn => &mut level.spawn,
+            Object::Floor(index) => &mut level.floors[index].position,
+            Object::Trigger(index) => &mut level.triggers[index].position,
+            Object::Pickup(index) => &mut level.pickups[index].position,
+            Object::Rival(index) => &mut level.rivals[index],
+            Object::Doodad(index) => &mut level.doodads[index].position,
+        }
+    }
+
+    /// The size, for objects that can be resized.
+    fn size(self, level: &mut Level) -> Option<&mut Vec2> {
+        match self {
+            Object::Floor(index) => Some(&mut level.floors[index].size),
+            Object::Trigger(index) => Some(&mut level.triggers[index].size),
+            _ => None,
+        }
+    }
+
+    /// The rotation, for objects that can be rotated.
+    fn rotation(self, level: &mut Level) -> Option<&mut f32> {
+        match self {
+            Object::Floor(index) => Some(&mut level.floors[index].rotation),
+            Object::Doodad(index) => Some(&mut level.doodads[index].rotation),
+            _ => None,
+        }
+    }
+
+    /// Where the object is drawn in the editor, which matches where it is
+    /// spawned in the game.
+    fn transform(self, level: &Level) -> Transform {
+        let (position, size, rotation, z) = match self {
+            Object::Spawn => (level.spawn, Vec2::splat(SPAWN_SIZE), 0.0, 100.0),
+            Object::Floor(index) => {
+                let floor = &level.floors[index];
+                (floor.position, floor.size, floor.rotation, LEVEL_Z)
+            }
+            Object::Trigger(index) => {
+                let zone = &level.triggers[index];
+                (zone.position, zone.size, 0.0, LEVEL_Z + 1.0)
+            }
+            Object::Pickup(index) => (
+                level.pickups[index].position,
+                Vec2::splat(PICKUP_SIZE),
+                0.0,
+                LEVEL_Z + 1.0,
+            ),
+            Object::Rival(index) => (level.rivals[index], Vec2::splat(SPAWN_SIZE), 0.0, 100.0),
+            Object::Doodad(index) => {
+                let doodad = &level.doodads[index];
+                (doodad.position, doodad.kind.size(), doodad.rotation, 50.0)
+            }
+        };
+
+        Transform::from_translation(position.extend(z))
+            .with_rotation(Quat::from_rotation_z(rotation))
+            .with_scale(size.extend(1.0))
+    }
+
+    /// Whether `point` is within the object's bounds.
+    fn contains(self, level: &Level, point: Vec2) -> bool {
+        let transform = self.transform(level);
+        let local = transform
+            .compute_matrix()
+            .inverse()
+            .transform_point3(point.extend(transform.translation.z));
+        local.x.abs() <= 0.5 && local.y.abs() <= 0.5
+    }
+
+    /// Remove the object from the level. There's always a spawn point, so it can't be removed.
+    fn remove(self, level: &mut Level) -> bool {
+        match self {
+            Object::Spawn => return false,
+            Object::Floor(index) => drop(level.floors.remove(index)),
+            Object::Trigger(index) => drop(level.triggers.remove(index)),
+            Object::Pickup(index) => drop(level.pickups.remove(index)),
+            Object::Rival(index) => drop(level.rivals.remove(index)),
+            Object::Doodad(index) => drop(level.doodads.remove(index)),
+        }
+        true
+    }
+
+    /// Switch to the next kind of trigger, power-up or doodad.
+    fn cycle_kind(self, level: &mut Level) -> bool {
+        match self {
+            Object::Trigger(index) => {
+                let kind = &mut level.triggers[index].kind;
+                *kind = next(&TriggerKind::ALL, |other| *other == *kind);
+            }
+            Object::Pickup(index) => {
+                let power_up = &mut level.pickups[index].power_up;
+                *power_up = next(&PowerUp::ALL, |other| other.same_kind(*power_up));
+            }
+            Object::Doodad(index) => {
+                let kind = &mut level.doodads[index].kind;
+                *kind = next(&DoodadKind::ALL, |other| *other == *kind);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn color(self, level: &Level, settings: &Settings) -> Color {
+        let colors = settings.palette.colors();
+        match self {
+            Object::Spawn => colors.player,
+            Object::Floor(_) => colors.level,
+            Object::Trigger(index) => level.triggers[index]
+                .kind
+                .color()
+                .unwrap_or(KILL_PLANE_COLOR),
+            Object::Pickup(index) => level.pickups[index].power_up.color(),
+            Object::Rival(_) => colors.ai,
+            Object::Doodad(_) => colors.doodad,
+        }
+    }
+}
+
+/// The item after the one matching `current`, wrapping around.
+fn next<T: Copy>(all: &[T], current: impl Fn(&T) -> bool) -> T {
+    let index = all.iter().position(current).map_or(0, |index| index + 1);
+    all[index % all.len()]
+}
+
+/// Draws an object of the level being edited.
+#[derive(Component)]
+struct StandIn {
+    object: Object,
+    color: Color,
+}
+
+/// Draws level geometry that can't be edited yet, for reference.
+#[derive(Component)]
+struct Backdrop;
+
+#[derive(Component)]
+struct EditorUi;
+
+#[derive(Component)]
+struct StatusText;
+
+fn open_editor(keyboard_input: Res<Input<KeyCode>>, mut state: ResMut<State<GameState>>) {
+    if keyboard_input.just_pressed(KeyCode::F1)
+        && matches!(state.current(), GameState::Menu | GameState::Playing)
+    {
+        // e.g. another state change was already queued this frame
+        if let Err(err) = state.set(GameState::Editor) {
+            log::warn!("can't open the editor: {err:?}");
+        }
+    }
+}
+
+fn remember_persistent_entities(mut commands: Commands, entities: Query<Entity>) {
+    commands.insert_resource(PersistentEntities(entities.iter().collect()));
+}
+
+/// Despawn everything that was spawned for the level, so it can be spawned
+/// fresh the next time it's played.
+fn clear_played_level(
+    mut commands: Commands,
+    persistent: Option<Res<PersistentEntities>>,
+    entities: Query<(Entity, Option<&Parent>)>,
+) {
+    let persistent = match persistent {
+        Some(persistent) => persistent,
+        None => return,
+    };
+
+    for (entity, parent) in &entities {
+        // children go along with their parents
+        let top_level = parent.map_or(true, |parent| persistent.0.contains(&parent.get()));
+        if top_level && !persistent.0.contains(&entity) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup_editor(
+    mut commands: Commands,
+    editor: Option<ResMut<EditorLevel>>,
+    settings: Res<Settings>,
+    font_assets: Res<FontAssets>,
+    meshes: Res<MeshAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut cameras: Query<&mut Camera, With<PlayerCamera>>,
+) {
+    let level = levels.get(&current_level.0).cloned().unwrap_or_default();
+    match editor {
+        // respawn the stand-ins, keeping the history from before playing
+        Some(mut editor) => editor.revision += 1,
+        None => commands.insert_resource(EditorLevel::new(level.clone())),
+    }
+
+    // the editor uses the whole window, even after split-screen play
+    for mut camera in &mut cameras {
+        camera.viewport = None;
+    }
+
+    let color = settings.palette.colors().level * 0.6;
+    let backdrop = level
+        .platforms
+        .iter()
+        .filter_map(|platform| Some((*platform.path.first()?, platform.size, 0.0)))
+        .chain(
+            level
+                .seesaws
+                .iter()
+                .map(|seesaw| (seesaw.pivot, seesaw.size, 0.0)),
+        )
+        .chain(
+            level
+                .conveyors
+                .iter()
+                .map(|conveyor| (conveyor.position, conveyor.size, conveyor.rotation)),
+        )
+        .chain(
+            level
+                .elevators
+                .iter()
+                .map(|elevator| (elevator.position, elevator.size, 0.0)),
+        );
+    for (position, size, rotation) in backdrop {
+        commands
+            .spawn_bundle(ColorMesh2dBundle {
+                mesh: meshes.floor.mesh.clone().into(),
+                material: materials.add(ColorMaterial::from(color)),
+                transform: Transform::from_translation(position.extend(LEVEL_Z))
+                    .with_rotation(Quat::from_rotation_z(rotation))
+                    .with_scale(size.extend(1.0)),
+                ..default()
+            })
+            .insert(Backdrop);
+    }
+
+    let text_style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 18.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+            ..default()
+        })
+        .insert(EditorUi)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(HELP, text_style.clone()));
+            parent
+                .spawn_bundle(TextBundle::from_section("", text_style))
+                .insert(StatusText);
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn cleanup_editor(
+    mut commands: Commands,
+    entities: Query<Entity, Or<(With<StandIn>, With<Backdrop>, With<EditorUi>)>>,
+    mut cameras: Query<&mut OrthographicProjection, With<PlayerCamera>>,
+) {
+    for entity in &entities {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for mut projection in &mut cameras {
+        projection.scale = 1.0;
+    }
+}
+
+fn cursor_position(
+    windows: &Windows,
+    cameras: &Query<(&Transform, &OrthographicProjection), With<PlayerCamera>>,
+) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    let (transform, projection) = cameras.iter().next()?;
+
+    let size = Vec2::new(window.width(), window.height());
+    Some(transform.translation.truncate() + (cursor - size / 2.0) * projection.scale)
+}
+
+/// Pan by dragging with the right or middle mouse button, and zoom with the wheel.
+fn move_camera(
+    windows: Res<Windows>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut wheel_events: EventReader<MouseWheel>,
+    mut last_cursor: Local<Option<Vec2>>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection), With<PlayerCamera>>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    let panning =
+        mouse_input.pressed(MouseButton::Right) || mouse_input.pressed(MouseButton::Middle);
+
+    for (mut transform, mut projection) in &mut cameras {
+        if let (true, Some(cursor), Some(last_cursor)) = (panning, cursor, *last_cursor) {
+            transform.translation -= ((cursor - last_cursor) * projection.scale).extend(0.0);
+        }
+
+        for event in wheel_events.iter() {
+            projection.scale =
+                (projection.scale * ZOOM_RATE.powf(-event.y.signum())).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
+
+    *last_cursor = cursor;
+}
+
+enum DragMode {
+    Move,
+    Resize,
+}
+
+struct Drag {
+    object: Object,
+    mode: DragMode,
+    /// Where the object is relative to the cursor, so it doesn't jump when grabbed.
+    grab: Vec2,
+    /// The level before dragging, saved for undo once something actually changes.
+    before: Option<Level>,
+}
+
+/// Select what's under the cursor, and drag it to move it, or with shift to resize it.
+fn edit_with_mouse(
+    mut editor: ResMut<EditorLevel>,
+    mut drag: Local<Option<Drag>>,
+    windows: Res<Windows>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<PlayerCamera>>,
+) {
+    let editor = &mut *editor;
+    let cursor = match cursor_position(&windows, &cameras) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    if mouse_input.just_pressed(MouseButton::Left) {
+        // the smallest object is most likely the one that was aimed for
+        let area = |object: &Object| object.transform(&editor.level).scale.truncate().length();
+        editor.selected = Object::all(&editor.level)
+            .into_iter()
+            .filter(|object| object.contains(&editor.level, cursor))
+            .min_by(|a, b| area(a).total_cmp(&area(b)));
+
+        let shift =
+            keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+        *drag = editor.selected.map(|object| Drag {
+            object,
+            mode: match object.size(&mut editor.level) {
+                Some(_) if shift => DragMode::Resize,
+                _ => DragMode::Move,
+            },
+            grab: *object.position(&mut editor.level) - cursor,
+            before: Some(editor.level.clone()),
+        });
+    }
+
+    if mouse_input.just_released(MouseButton::Left) {
+        *drag = None;
+    }
+
+    let drag = match &mut *drag {
+        Some(drag) => drag,
+        None => return,
+    };
+
+    let mut level = editor.level.clone();
+    match drag.mode {
+        DragMode::Move => {
+            *drag.object.position(&mut level) = editor.snap(cursor + drag.grab);
+        }
+        DragMode::Resize => {
+            let center = *drag.object.position(&mut level);
+            let rotation = drag
+                .object
+                .rotation(&mut level)
+                .copied()
+                .unwrap_or_default();
+            let local = Quat::from_rotation_z(-rotation) * (cursor - center).extend(0.0);
+            let size = editor
+                .snap(local.truncate().abs() * 2.0)
+                .max(Vec2::splat(GRID_SIZE));
+            if let Some(current) = drag.object.size(&mut level) {
+                *current = size;
+            }
+        }
+    }
+
+    let transform = drag.object.transform(&level);
+    if transform != drag.object.transform(&editor.level) {
+        if let Some(before) = drag.before.take() {
+            editor.push_undo(before);
+        }
+        editor.level = level;
+    }
+}
+
+fn edit_with_keys(
+    mut editor: ResMut<EditorLevel>,
+    windows: Res<Windows>,
+    keyboard_input: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    asset_settings: Res<AssetServerSettings>,
+    current_level: Res<CurrentLevel>,
+    cameras: Query<(&Transform, &OrthographicProjection), With<PlayerCamera>>,
+) {
+    let editor = &mut *editor;
+    let pressed = |key| keyboard_input.just_pressed(key);
+    let ctrl =
+        keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    let shift = keyboard_input.pressed(KeyCode::LShift) || keyboard_input.pressed(KeyCode::RShift);
+
+    if ctrl {
+        if (pressed(KeyCode::Z) && shift) || pressed(KeyCode::Y) {
+            editor.redo();
+        } else if pressed(KeyCode::Z) {
+            editor.undo();
+        } else if pressed(KeyCode::S) {
+            let saved = save_level(
+                &editor.level,
+                &asset_server,
+                &asset_settings,
+                &current_level,
+            );
+            editor.message = match saved {
+                Ok(path) => format!("saved to {path}"),
+                Err(err) => format!("failed to save: {err}"),
+            };
+            log::info!("{}", editor.message);
+        }
+        return;
+    }
+
+    if pressed(KeyCode::G) {
+        editor.snap = !editor.snap;
+    }
+    if pressed(KeyCode::Escape) {
+        editor.selected = None;
+    }
+
+    // place new objects under the cursor
+    if let Some(cursor) = cursor_position(&windows, &cameras) {
+        let position = editor.snap(cursor);
+        let level = &editor.level;
+        let placed = if pressed(KeyCode::Key1) {
+            Some(Object::Floor(level.floors.len()))
+        } else if pressed(KeyCode::Key2) {
+            Some(Object::Trigger(level.triggers.len()))
+        } else if pressed(KeyCode::Key3) {
+            Some(Object::Pickup(level.pickups.len()))
+        } else if pressed(KeyCode::Key4) {
+            Some(Object::Rival(level.rivals.len()))
+        } else if pressed(KeyCode::Key5) {
+            Some(Object::Doodad(level.doodads.len()))
+        } else {
+            None
+        };
+
+        if let Some(object) = placed {
+            editor.checkpoint();
+            let level = &mut editor.level;
+            match object {
+                Object::Floor(_) => level.floors.push(Floor {
+                    position,
+                    size: Vec2::new(200.0, 15.0),
+                    rotation: 0.0,
+                }),
+                Object::Trigger(_) => level.triggers.push(TriggerZone {
+                    position,
+                    size: Vec2::new(40.0, 80.0),
+                    kind: TriggerKind::Checkpoint,
+                }),
+                Object::Pickup(_) => level
+                    .pickups
+                    .push(PickupSpawn::new(position, PowerUp::ALL[0])),
+                Object::Rival(_) => level.rivals.push(position),
+                Object::Doodad(_) => level.doodads.push(DoodadPlacement {
+                    kind: DoodadKind::Square,
+                    position,
+                    rotation: 0.0,
+                }),
+                Object::Spawn => unreachable!(),
+            }
+            editor.selected = Some(object);
+            editor.revision += 1;
+        }
+    }
+
+    let selected = match editor.selected {
+        Some(selected) => selected,
+        None => return,
+    };
+
+    if pressed(KeyCode::Delete) || pressed(KeyCode::Back) {
+        let before = editor.level.clone();
+        if selected.remove(&mut editor.level) {
+            editor.push_undo(before);
+            editor.reload();
+        }
+    } else if pressed(KeyCode::Tab) {
+        let before = editor.level.clone();
+        if selected.cycle_kind(&mut editor.level) {
+            editor.push_undo(before);
+            editor.revision += 1;
+        }
+    } else if pressed(KeyCode::Q) || pressed(KeyCode::E) {
+        let step = if editor.snap {
+            ROTATION_STEP
+        } else {
+            FINE_ROTATION_STEP
+        };
+        let step = if pressed(KeyCode::Q) { step } else { -step };
+
+        let before = editor.level.clone();
+        if let Some(rotation) = selected.rotation(&mut editor.level) {
+            *rotation = (*rotation + step) % (2.0 * PI);
+            editor.push_undo(before);
+        }
+    }
+}
+
+/// Write the level back to the file it was loaded from, returning its path.
+/// The path is resolved the same way the asset server finds the file.
+#[cfg(not(target_arch = "wasm32"))]
+fn save_level(
+    level: &Level,
+    asset_server: &AssetServer,
+    asset_settings: &AssetServerSettings,
+    current_level: &CurrentLevel,
+) -> Result<String, String> {
+    let asset_path = asset_server
+        .get_handle_path(&current_level.0)
+        .ok_or("the level wasn't loaded from a file")?;
+    let path = FileAssetIo::get_root_path()
+        .join(&asset_settings.asset_folder)
+        .join(asset_path.path());
+
+    let contents = ron::ser::to_string_pretty(level, ron::ser::PrettyConfig::new())
+        .map_err(|err| err.to_string())?;
+    std::fs::write(&path, contents).map_err(|err| err.to_string())?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+// TODO: offer the level as a download on the web
+#[cfg(target_arch = "wasm32")]
+fn save_level(
+    _level: &Level,
+    _asset_server: &AssetServer,
+    _asset_settings: &AssetServerSettings,
+    _current_level: &CurrentLevel,
+) -> Result<String, String> {
+    Err("levels can't be saved on the web".to_string())
+}
+
+/// Keep the stand-ins in line with the level, respawning all of them when
+/// objects were added or removed.
+fn update_stand_ins(
+    mut commands: Commands,
+    editor: Res<EditorLevel>,
+    settings: Res<Settings>,
+    meshes: Res<MeshAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut revision: Local<Option<u32>>,
+    mut stand_ins: Query<(Entity, &StandIn, &mut Transform, &Handle<ColorMaterial>)>,
+) {
+    if *revision != Some(editor.revision) {
+        *revision = Some(editor.revision);
+
+        for (entity, ..) in &stand_ins {
+            commands.entity(entity).despawn();
+        }
+
+        for object in Object::all(&editor.level) {
+            let (mesh, texture) = match object {
+                Object::Floor(_) | Object::Trigger(_) => (meshes.floor.mesh.clone(), None),
+                Object::Doodad(index) => {
+                    let asset = meshes.doodad(editor.level.doodads[index].kind);
+                    let texture = materials
+                        .get(&asset.material)
+                        .and_then(|material| material.texture.clone());
+                    (asset.mesh.clone(), texture)
+                }
+                Object::Spawn | Object::Pickup(_) | Object::Rival(_) => {
+                    (meshes.player.mesh.clone(), None)
+                }
+            };
+            let color = object.color(&editor.level, &settings);
+
+            commands
+                .spawn_bundle(ColorMesh2dBundle {
+                    mesh: mesh.into(),
+                    material: materials.add(ColorMaterial { color, texture }),
+                    transform: object.transform(&editor.level),
+                    ..default()
+                })
+                .insert(StandIn { object, color });
+        }
+        return;
+    }
+
+    for (_, stand_in, mut transform, material) in &mut stand_ins {
+        *transform = stand_in.object.transform(&editor.level);
+
+        let color = if editor.selected == Some(stand_in.object) {
+            highlight(stand_in.color)
+        } else {
+            stand_in.color
+        };
+        // only touch the material when needed, since that re-uploads it
+        if materials
+            .get(material)
+            .map_or(false, |material| material.color != color)
+        {
+            if let Some(material) = materials.get_mut(material) {
+                material.color = color;
+            }
+        }
+    }
+}
+
+/// Brighten a color, to show what's selected.
+fn highlight(color: Color) -> Color {
+    let lighten = |channel: f32| channel + (1.0 - channel) * 0.5;
+    Color::rgba(
+        lighten(color.r()),
+        lighten(color.g()),
+        lighten(color.b()),
+        color.a().max(0.6),
+    )
+}
+
+fn update_status(editor: Res<EditorLevel>, mut texts: Query<&mut Text, With<StatusText>>) {
+    let selected = match editor.selected {
+        Some(object) => {
+            let position = object.transform(&editor.level).translation;
+            format!("{object:?} at ({:.0}, {:.0})", position.x, position.y)
+        }
+        None => "nothing selected".to_string(),
+    };
+    let status = format!(
+        "{selected}  |  grid {}  |  {} undo, {} redo  {}",
+        if editor.snap { "on" } else { "off" },
+        editor.undo.len(),
+        editor.redo.len(),
+        editor.message,
+    );
+
+    for mut text in &mut texts {
+        if text.sections[0].value != status {
+            text.sections[0].value = status.clone();
+        }
+    }
+}
+
+/// Play the level as edited so far, without saving it.
+fn play_level(
+    keyboard_input: Res<Input<KeyCode>>,
+    editor: Res<EditorLevel>,
+    current_level: Res<CurrentLevel>,
+    mut levels: ResMut<Assets<Level>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    if let Some(level) = levels.get_mut(&current_level.0) {
+        *level = editor.level.clone();
+    }
+    if let Err(err) = state.set(GameState::Playing) {
+        log::warn!("can't play the level: {err:?}");
+    }
+}
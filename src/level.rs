@@ -1,28 +1,117 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
 use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::loading::MeshAssets;
+use crate::background::BackgroundLayer;
+use crate::doodad::DoodadPlacement;
+use crate::loading::{LevelAssets, MeshAssets};
+use crate::platforms::{Conveyor, Elevator, MovingPlatform, Seesaw};
+use crate::powerups::PickupSpawn;
+use crate::triggers::TriggerZone;
 use crate::{physics, GameState};
 
+/// Level geometry is drawn in front of the background layers, but behind everything else.
+pub const LEVEL_Z: f32 = 10.0;
+
 pub struct LevelPlugin;
 
+/// This plugin loads level definitions from `.level` files (written in RON)
+/// and spawns the level geometry they describe.
 impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_floor));
+        app.add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .add_system_set(SystemSet::on_exit(GameState::Loading).with_system(select_first_level))
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_floors));
     }
 }
 
-fn spawn_floor(mut commands: Commands, meshes: Res<MeshAssets>) {
-    commands
-        .spawn_bundle(ColorMesh2dBundle {
-            material: meshes.floor.material.clone(),
-            mesh: meshes.floor.mesh.clone().into(),
-            ..default()
+/// A level definition, as loaded from a `.level` file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5a7c6d3e-2b1f-4e8a-9c0d-7f3e1a2b4c5d"]
+#[serde(default)]
+pub struct Level {
+    /// Where the player starts.
+    pub spawn: Vec2,
+    pub floors: Vec<Floor>,
+    pub platforms: Vec<MovingPlatform>,
+    pub seesaws: Vec<Seesaw>,
+    pub conveyors: Vec<Conveyor>,
+    pub elevators: Vec<Elevator>,
+    pub triggers: Vec<TriggerZone>,
+    pub pickups: Vec<PickupSpawn>,
+    /// Where computer-controlled rival clusters start.
+    pub rivals: Vec<Vec2>,
+    pub doodads: Vec<DoodadPlacement>,
+    /// Parallax layers, from the furthest away to the closest.
+    pub background: Vec<BackgroundLayer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Floor {
+    pub position: Vec2,
+    pub size: Vec2,
+    /// Rotation in radians, counter-clockwise.
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+/// The level currently being played.
+pub struct CurrentLevel(pub Handle<Level>);
+
+#[derive(Default)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level = ron::de::from_bytes::<Level>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
         })
-        .insert(RigidBody::Fixed)
-        .insert(meshes.floor.collider.clone())
-        .insert(physics::CollideGroups::level())
-        .insert_bundle(TransformBundle::from(
-            Transform::from_xyz(0.0, -100.0, 0.0).with_scale(Vec3::new(1000.0, 15.0, 1.0)),
-        ));
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level"]
+    }
+}
+
+fn select_first_level(mut commands: Commands, levels: Res<LevelAssets>) {
+    commands.insert_resource(CurrentLevel(levels.first.clone()));
+}
+
+fn spawn_floors(
+    mut commands: Commands,
+    meshes: Res<MeshAssets>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        Some(level) => level,
+        None => return,
+    };
+
+    for floor in &level.floors {
+        commands
+            .spawn_bundle(ColorMesh2dBundle {
+                material: meshes.floor.material.clone(),
+                mesh: meshes.floor.mesh.clone().into(),
+                ..default()
+            })
+            .insert(RigidBody::Fixed)
+            .insert(meshes.floor.collider.clone())
+            .insert(physics::CollideGroups::level())
+            .insert_bundle(TransformBundle::from(
+                Transform::from_translation(floor.position.extend(LEVEL_Z))
+                    .with_rotation(Quat::from_rotation_z(floor.rotation))
+                    .with_scale(floor.size.extend(1.0)),
+            ));
+    }
 }
@@ -5,21 +5,65 @@ use bevy_rapier2d::prelude::*;
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 
 mod actions;
+mod ai;
 mod audio;
+mod background;
+mod camera;
+mod checkpoint;
+mod cluster;
+#[cfg(feature = "dev")]
+mod console;
 mod doodad;
+#[cfg(feature = "dev")]
+mod editor;
 mod level;
 mod loading;
+mod magnet;
 mod menu;
+mod net;
+mod particles;
 mod physics;
+mod platforms;
 mod player;
+mod powerups;
+mod score;
+mod settings;
+mod sfx;
+mod soft;
+mod stress;
+mod triggers;
 
 use actions::ActionsPlugin;
+use ai::AiPlugin;
 use audio::InternalAudioPlugin;
+use background::BackgroundPlugin;
+use camera::CameraPlugin;
+use checkpoint::CheckpointPlugin;
+use cluster::ClusterPlugin;
+#[cfg(feature = "dev")]
+use console::ConsolePlugin;
 use doodad::DoodadPlugin;
+#[cfg(feature = "dev")]
+use editor::EditorPlugin;
 use level::LevelPlugin;
 use loading::LoadingPlugin;
+use magnet::MagnetPlugin;
 use menu::MenuPlugin;
+use net::NetPlugin;
+use particles::ParticlesPlugin;
+use physics::PhysicsPlugin;
+use platforms::PlatformsPlugin;
 use player::PlayerPlugin;
+use powerups::PowerUpsPlugin;
+use score::ScorePlugin;
+use settings::SettingsPlugin;
+use sfx::SfxPlugin;
+use soft::SoftAttachmentPlugin;
+use triggers::TriggersPlugin;
+
+pub use net::NetRole;
+pub use settings::Settings;
+pub use stress::{StressTest, StressTestPlugin};
 
 // This example game uses States to separate logic
 // See https://bevy-cheatbook.github.io/programming/states.html
@@ -32,6 +76,9 @@ enum GameState {
     Playing,
     /// Here the menu is drawn and waiting for player interaction
     Menu,
+    /// In dev builds, the level can be edited here and played right away
+    #[cfg(feature = "dev")]
+    Editor,
 }
 
 pub struct GamePlugin;
@@ -40,17 +87,35 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_state(GameState::Loading)
             .add_plugin(<RapierPhysicsPlugin>::pixels_per_meter(50.0))
+            .add_plugin(PhysicsPlugin)
+            .add_plugin(SettingsPlugin)
             .add_plugin(LoadingPlugin)
+            .add_plugin(CameraPlugin)
             .add_plugin(MenuPlugin)
             .add_plugin(ActionsPlugin)
             .add_plugin(InternalAudioPlugin)
+            .add_plugin(SfxPlugin)
             .add_plugin(PlayerPlugin)
+            .add_plugin(AiPlugin)
+            .add_plugin(ClusterPlugin)
+            .add_plugin(SoftAttachmentPlugin)
             .add_plugin(LevelPlugin)
-            .add_plugin(DoodadPlugin);
+            .add_plugin(PlatformsPlugin)
+            .add_plugin(TriggersPlugin)
+            .add_plugin(CheckpointPlugin)
+            .add_plugin(PowerUpsPlugin)
+            .add_plugin(MagnetPlugin)
+            .add_plugin(BackgroundPlugin)
+            .add_plugin(DoodadPlugin)
+            .add_plugin(ParticlesPlugin)
+            .add_plugin(NetPlugin)
+            .add_plugin(ScorePlugin);
 
         #[cfg(feature = "dev")]
         app.add_plugin(RapierDebugRenderPlugin::default())
             .add_plugin(FrameTimeDiagnosticsPlugin::default())
-            .add_plugin(LogDiagnosticsPlugin::default());
+            .add_plugin(LogDiagnosticsPlugin::default())
+            .add_plugin(EditorPlugin)
+            .add_plugin(ConsolePlugin);
     }
 }
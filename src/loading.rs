@@ -1,8 +1,15 @@
+use std::collections::HashMap;
+use std::mem::{self, Discriminant};
+
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use bevy_kira_audio::AudioSource;
 use bevy_rapier2d::prelude::*;
 
+use crate::doodad::DoodadKind;
+use crate::level::Level;
+use crate::player::{PlayerId, MAX_PLAYERS};
+use crate::powerups::PowerUp;
 use crate::GameState;
 
 pub struct LoadingPlugin;
@@ -16,9 +23,13 @@ impl Plugin for LoadingPlugin {
             LoadingState::new(GameState::Loading)
                 .with_collection::<FontAssets>()
                 .with_collection::<AudioAssets>()
+                .with_collection::<MusicAssets>()
+                .with_collection::<TextureAssets>()
+                .with_collection::<LevelAssets>()
                 .continue_to_state(GameState::Menu),
         )
-        .add_system_set(SystemSet::on_enter(GameState::Loading).with_system(build_meshes));
+        // meshes need the loaded textures, which are inserted before the state changes
+        .add_system_set(SystemSet::on_exit(GameState::Loading).with_system(build_meshes));
     }
 }
 
@@ -35,12 +46,79 @@ pub struct FontAssets {
 pub struct AudioAssets {
     #[asset(path = "audio/flying.ogg")]
     pub flying: Handle<AudioSource>,
+    #[asset(path = "audio/impact.wav")]
+    pub impact: Handle<AudioSource>,
+    #[asset(path = "audio/absorb.wav")]
+    pub absorb: Handle<AudioSource>,
+}
+
+#[derive(AssetCollection)]
+pub struct MusicAssets {
+    #[asset(path = "audio/music/menu.wav")]
+    pub menu: Handle<AudioSource>,
+    #[asset(path = "audio/music/base.wav")]
+    pub base: Handle<AudioSource>,
+    #[asset(path = "audio/music/rhythm.wav")]
+    pub rhythm: Handle<AudioSource>,
+    #[asset(path = "audio/music/lead.wav")]
+    pub lead: Handle<AudioSource>,
+}
+
+#[derive(AssetCollection)]
+pub struct TextureAssets {
+    #[asset(path = "textures/player.png")]
+    pub player: Handle<Image>,
+    #[asset(path = "textures/square.png")]
+    pub square: Handle<Image>,
+    #[asset(path = "textures/ball.png")]
+    pub ball: Handle<Image>,
+    #[asset(path = "textures/plank.png")]
+    pub plank: Handle<Image>,
+}
+
+impl TextureAssets {
+    pub fn doodad(&self, kind: DoodadKind) -> Handle<Image> {
+        match kind {
+            DoodadKind::Square => self.square.clone(),
+            DoodadKind::Ball => self.ball.clone(),
+            DoodadKind::Plank => self.plank.clone(),
+        }
+    }
+}
+
+#[derive(AssetCollection)]
+pub struct LevelAssets {
+    #[asset(path = "levels/first.level")]
+    pub first: Handle<Level>,
 }
 
 pub struct MeshAssets {
-    pub square: MeshAsset,
+    pub doodads: HashMap<DoodadKind, MeshAsset>,
     pub player: MeshAsset,
+    /// Materials for the other local players, in order.
+    pub other_players: Vec<Handle<ColorMaterial>>,
+    /// Material for computer-controlled clusters.
+    pub ai: Handle<ColorMaterial>,
     pub floor: MeshAsset,
+    /// Materials for pickups, one for each kind of power-up.
+    pub pickups: HashMap<Discriminant<PowerUp>, Handle<ColorMaterial>>,
+}
+
+impl MeshAssets {
+    pub fn doodad(&self, kind: DoodadKind) -> &MeshAsset {
+        &self.doodads[&kind]
+    }
+
+    pub fn player_material(&self, player: PlayerId) -> Handle<ColorMaterial> {
+        match player.0.checked_sub(1) {
+            Some(other) => self.other_players[other].clone(),
+            None => self.player.material.clone(),
+        }
+    }
+
+    pub fn pickup_material(&self, power_up: PowerUp) -> Handle<ColorMaterial> {
+        self.pickups[&mem::discriminant(&power_up)].clone()
+    }
 }
 
 pub struct MeshAsset {
@@ -51,23 +129,34 @@ pub struct MeshAsset {
 
 fn build_meshes(
     mut commands: Commands,
+    textures: Res<TextureAssets>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    let square = {
-        let mesh = meshes.add(shape::Cube::default().into());
-        let material = materials.add(ColorMaterial {
-            color: Color::BLUE,
-            ..default()
-        });
-        let collider = Collider::round_cuboid(0.5, 0.5, 0.05);
+    let square_mesh = meshes.add(shape::Cube::default().into());
+    let circle_mesh = meshes.add(shape::Circle::default().into());
 
-        MeshAsset {
-            mesh,
-            material,
-            collider,
-        }
-    };
+    let doodads = DoodadKind::ALL
+        .into_iter()
+        .map(|kind| {
+            let mesh = match kind {
+                DoodadKind::Square | DoodadKind::Plank => square_mesh.clone(),
+                DoodadKind::Ball => circle_mesh.clone(),
+            };
+            // the color is filled in from the palette
+            let material = materials.add(ColorMaterial {
+                color: Color::WHITE,
+                texture: Some(textures.doodad(kind)),
+            });
+
+            let asset = MeshAsset {
+                mesh,
+                material,
+                collider: kind.collider(),
+            };
+            (kind, asset)
+        })
+        .collect();
 
     let floor = {
         let material = materials.add(ColorMaterial {
@@ -77,29 +166,54 @@ fn build_meshes(
 
         MeshAsset {
             material,
-            mesh: square.mesh.clone(),
-            collider: square.collider.clone(),
+            mesh: square_mesh,
+            collider: Collider::round_cuboid(0.5, 0.5, 0.05),
         }
     };
 
     let player = {
-        let mesh = meshes.add(shape::Circle::default().into());
+        // the color is filled in from the palette
         let material = materials.add(ColorMaterial {
-            color: Color::RED,
-            ..default()
+            color: Color::WHITE,
+            texture: Some(textures.player.clone()),
         });
         let collider = Collider::ball(0.5);
 
         MeshAsset {
-            mesh,
+            mesh: circle_mesh,
             material,
             collider,
         }
     };
 
+    // the colors are filled in from the palette
+    let other_players = (1..MAX_PLAYERS)
+        .map(|_| {
+            materials.add(ColorMaterial {
+                color: Color::WHITE,
+                texture: Some(textures.player.clone()),
+            })
+        })
+        .collect();
+    let ai = materials.add(ColorMaterial {
+        color: Color::WHITE,
+        texture: Some(textures.player.clone()),
+    });
+
+    let pickups = PowerUp::ALL
+        .into_iter()
+        .map(|power_up| {
+            let material = materials.add(ColorMaterial::from(power_up.color()));
+            (mem::discriminant(&power_up), material)
+        })
+        .collect();
+
     commands.insert_resource(MeshAssets {
-        square,
+        doodads,
         player,
+        other_players,
+        ai,
         floor,
+        pickups,
     });
 }
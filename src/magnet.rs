@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::doodad::Doodad;
+use crate::powerups::ActivePowerUps;
+use crate::{physics, GameState};
+
+pub struct MagnetPlugin;
+
+/// This plugin makes a player with the magnet power-up pull in nearby doodads.
+impl Plugin for MagnetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::Playing).with_system(attract_doodads));
+    }
+}
+
+/// Pull strength at the edge of the magnet's radius, growing toward the player.
+const MAGNET_FORCE: f32 = 200.0;
+
+/// Marks doodads currently being pulled by a magnet.
+#[derive(Component)]
+struct Attracted;
+
+fn attract_doodads(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    magnets: Query<(&GlobalTransform, &ActivePowerUps)>,
+    doodads: Query<&GlobalTransform, With<Doodad>>,
+    attracted: Query<Entity, With<Attracted>>,
+) {
+    let mut in_range = HashSet::new();
+
+    for (transform, active) in &magnets {
+        let radius = match active.magnet_radius() {
+            Some(radius) => radius,
+            None => continue,
+        };
+
+        let center = transform.translation().truncate();
+        let filter = QueryFilter::only_dynamic().groups(physics::CollideGroups::doodad().into());
+
+        rapier_context.intersections_with_shape(
+            center,
+            0.0,
+            &Collider::ball(radius),
+            filter,
+            |entity| {
+                if let Ok(doodad_transform) = doodads.get(entity) {
+                    let offset = center - doodad_transform.translation().truncate();
+                    let distance = offset.length().max(1.0);
+                    // pull harder the closer the doodad gets
+                    let strength = MAGNET_FORCE * (radius / distance).min(4.0);
+
+                    commands
+                        .entity(entity)
+                        .insert(ExternalForce {
+                            force: offset / distance * strength,
+                            torque: 0.0,
+                        })
+                        .insert(Attracted);
+                    in_range.insert(entity);
+                }
+                true
+            },
+        );
+    }
+
+    // let go of anything that's out of range, or absorbed, or the magnet ran out
+    for entity in &attracted {
+        if !in_range.contains(&entity) {
+            commands
+                .entity(entity)
+                .remove::<ExternalForce>()
+                .remove::<Attracted>();
+        }
+    }
+}
@@ -9,19 +9,35 @@ use bevy::winit::WinitWindows;
 use bevy::DefaultPlugins;
 use winit::window::Icon;
 
-use clusterjunk::GamePlugin;
+use clusterjunk::{GamePlugin, NetRole, Settings};
 
 fn main() {
-    App::new()
-        .insert_resource(Msaa { samples: 1 })
+    // load settings up front so the window is created with the right size and mode
+    let settings = Settings::load();
+    let net_role = match NetRole::from_args(std::env::args().skip(1)) {
+        Ok(net_role) => net_role,
+        Err(err) => {
+            eprintln!("{err}\nusage: clusterjunk [--host [port] | --join <address>[:port]]");
+            std::process::exit(2);
+        }
+    };
+
+    let mut app = App::new();
+    if let Some(net_role) = net_role {
+        app.insert_resource(net_role);
+    }
+
+    app.insert_resource(Msaa { samples: 1 })
         .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
         .insert_resource(WindowDescriptor {
-            width: 800.,
-            height: 600.,
+            width: settings.window.width,
+            height: settings.window.height,
+            mode: settings.window.mode.into(),
             title: "Clusterjunk!".to_string(),
             canvas: Some("#bevy".to_owned()),
             ..default()
         })
+        .insert_resource(settings)
         .add_plugins(DefaultPlugins)
         .add_plugin(GamePlugin)
         .add_startup_system(set_window_icon)
@@ -1,17 +1,24 @@
 use bevy::prelude::*;
 
 use crate::loading::FontAssets;
+use crate::player::MAX_PLAYERS;
+use crate::settings::Settings;
 use crate::GameState;
 
 pub struct MenuPlugin;
 
-/// This plugin is responsible for the game menu (containing only one button...)
+/// This plugin is responsible for the game menu, with a play button and a
+/// button choosing how many local players there are.
 /// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonColors>()
             .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup_menu))
-            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(click_play_button))
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(click_menu_buttons)
+                    .with_system(update_players_label),
+            )
             .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(cleanup_menu));
     }
 }
@@ -30,65 +37,135 @@ impl Default for ButtonColors {
     }
 }
 
+#[derive(Component)]
+struct Menu;
+
+#[derive(Component, Clone, Copy)]
+enum MenuButton {
+    Play,
+    /// Cycles through the number of local players.
+    Players,
+}
+
+#[derive(Component)]
+struct PlayersLabel;
+
+fn players_label(settings: &Settings) -> String {
+    match settings.player_count() {
+        1 => "1 Player".to_string(),
+        count => format!("{count} Players"),
+    }
+}
+
 fn setup_menu(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     button_colors: Res<ButtonColors>,
+    settings: Res<Settings>,
 ) {
-    commands.spawn_bundle(Camera2dBundle::default());
+    let text_style = |font_size| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+
     commands
-        .spawn_bundle(ButtonBundle {
+        .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Px(120.0), Val::Px(50.0)),
                 margin: UiRect::all(Val::Auto),
-                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::ColumnReverse,
                 align_items: AlignItems::Center,
                 ..default()
             },
-            color: button_colors.normal,
+            color: Color::NONE.into(),
             ..default()
         })
+        .insert(Menu)
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle {
-                text: Text {
-                    sections: vec![TextSection {
-                        value: "Play".to_string(),
-                        style: TextStyle {
-                            font: font_assets.fira_sans.clone(),
-                            font_size: 40.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
+            for (button, size, label, font_size) in [
+                (
+                    MenuButton::Play,
+                    Size::new(Val::Px(120.0), Val::Px(50.0)),
+                    "Play".to_string(),
+                    40.0,
+                ),
+                (
+                    MenuButton::Players,
+                    Size::new(Val::Px(160.0), Val::Px(40.0)),
+                    players_label(&settings),
+                    30.0,
+                ),
+            ] {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size,
+                            margin: UiRect::all(Val::Px(10.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
                         },
-                    }],
-                    alignment: default(),
-                },
-                ..default()
-            });
+                        color: button_colors.normal,
+                        ..default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        let mut text = parent.spawn_bundle(TextBundle {
+                            text: Text {
+                                sections: vec![TextSection {
+                                    value: label,
+                                    style: text_style(font_size),
+                                }],
+                                alignment: default(),
+                            },
+                            ..default()
+                        });
+
+                        if let MenuButton::Players = button {
+                            text.insert(PlayersLabel);
+                        }
+                    });
+            }
         });
 }
 
-fn click_play_button(
+fn click_menu_buttons(
     button_colors: Res<ButtonColors>,
     mut state: ResMut<State<GameState>>,
+    mut settings: ResMut<Settings>,
     mut interaction_query: Query<
-        (&Interaction, &mut UiColor),
+        (&Interaction, &MenuButton, &mut UiColor),
         (Changed<Interaction>, With<Button>),
     >,
 ) {
-    for (interaction, mut color) in &mut interaction_query {
-        match *interaction {
-            Interaction::Clicked => {
+    for (interaction, button, mut color) in &mut interaction_query {
+        match (*interaction, button) {
+            (Interaction::Clicked, MenuButton::Play) => {
                 state.set(GameState::Playing).unwrap();
             }
-            Interaction::Hovered => {
+            (Interaction::Clicked, MenuButton::Players) => {
+                settings.local_players = settings.player_count() % MAX_PLAYERS + 1;
+            }
+            (Interaction::Hovered, _) => {
                 *color = button_colors.hovered;
             }
-            Interaction::None => {
+            (Interaction::None, _) => {
                 *color = button_colors.normal;
             }
         }
     }
 }
 
-fn cleanup_menu(mut commands: Commands, button: Query<Entity, With<Button>>) {
-    commands.entity(button.single()).despawn_recursive();
+fn update_players_label(settings: Res<Settings>, mut labels: Query<&mut Text, With<PlayersLabel>>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for mut text in &mut labels {
+        text.sections[0].value = players_label(&settings);
+    }
+}
+
+fn cleanup_menu(mut commands: Commands, menu: Query<Entity, With<Menu>>) {
+    commands.entity(menu.single()).despawn_recursive();
 }
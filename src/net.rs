@@ -0,0 +1,948 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f32::consts::{PI, TAU};
+use std::io::{self, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use bevy::{log, prelude::*};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::actions::Actions;
+use crate::ai::Ai;
+use crate::doodad::{Doodad, DoodadKind};
+use crate::level::{CurrentLevel, Level};
+use crate::loading::MeshAssets;
+use crate::platforms::Platform;
+use crate::player::{self, Player, PlayerId, MAX_PLAYERS};
+use crate::powerups::{Pickup, PowerUp};
+use crate::settings::Settings;
+use crate::soft::SoftAttached;
+use crate::GameState;
+
+pub struct NetPlugin;
+
+/// This plugin runs online versus games over UDP. The host runs the whole
+/// simulation, with a player for each client that joins, and regularly sends
+/// everyone a snapshot of what's in the level. Clients only send their
+/// input, and draw the snapshots, interpolating between them.
+/// Online play is enabled by inserting a [`NetRole`].
+impl Plugin for NetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_networking))
+            // received before anything else runs, so input is there for the players to move
+            .add_system_to_stage(CoreStage::PreUpdate, receive_inputs)
+            .add_system_to_stage(CoreStage::PreUpdate, receive_snapshots)
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(drop_silent_clients)
+                    .with_system(send_snapshots)
+                    .with_system(send_input)
+                    .with_system(show_snapshots),
+            );
+    }
+}
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+const SNAPSHOT_INTERVAL: f32 = 1.0 / 20.0;
+/// Clients draw the game this far behind the latest snapshot, so there is
+/// usually a newer one to interpolate toward.
+const INTERPOLATION_DELAY: f64 = 0.1;
+/// If the client's clock drifts further than this from the snapshots, it jumps back in line.
+const MAX_CLOCK_DRIFT: f64 = 0.25;
+/// Anyone that hasn't been heard from in this long is considered gone.
+const TIMEOUT: f64 = 5.0;
+/// How often clients ask to join until the host answers.
+const JOIN_INTERVAL: f64 = 1.0;
+/// Snapshots are split over several packets, to stay well under the datagram size limit.
+const ENTITIES_PER_PACKET: usize = 64;
+const MAX_PACKET_SIZE: usize = 65536;
+
+/// Which side of an online game this is.
+#[derive(Debug, Clone, Copy)]
+pub enum NetRole {
+    /// Runs the game, and lets clients join on this port.
+    Host { port: u16 },
+    /// Joins the game hosted at this address.
+    Client { host: SocketAddr },
+}
+
+impl NetRole {
+    /// Read the role from command line arguments, either `--host [port]` or
+    /// `--join <address>[:port]`. Without either, the game is offline.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        match args.next().as_deref() {
+            None => Ok(None),
+            Some("--host") => {
+                let port = match args.next() {
+                    Some(port) => port.parse().map_err(|_| format!("invalid port: {port}"))?,
+                    None => DEFAULT_PORT,
+                };
+                Ok(Some(NetRole::Host { port }))
+            }
+            Some("--join") => {
+                let address = args.next().ok_or("--join needs the host's address")?;
+                let with_port = if address.contains(':') {
+                    address.clone()
+                } else {
+                    format!("{address}:{DEFAULT_PORT}")
+                };
+
+                // the host listens on IPv4, so prefer that for names like localhost
+                let addresses: Vec<_> = with_port
+                    .to_socket_addrs()
+                    .map_err(|err| format!("invalid address {address}: {err}"))?
+                    .collect();
+                let host = addresses
+                    .iter()
+                    .find(|address| address.is_ipv4())
+                    .or_else(|| addresses.first())
+                    .copied()
+                    .ok_or_else(|| format!("{address} didn't resolve to anything"))?;
+
+                Ok(Some(NetRole::Client { host }))
+            }
+            Some(arg) => Err(format!("unknown argument: {arg}")),
+        }
+    }
+}
+
+/// Whether this is a client of an online game, which doesn't simulate
+/// anything itself.
+pub fn is_client(net_role: &Option<Res<NetRole>>) -> bool {
+    matches!(net_role.as_deref(), Some(NetRole::Client { .. }))
+}
+
+/// How many views the screen is split into. Clients only show their own
+/// player, however many local players the settings ask for.
+pub fn view_count(settings: &Settings, net_role: &Option<Res<NetRole>>) -> usize {
+    if is_client(net_role) {
+        1
+    } else {
+        settings.player_count()
+    }
+}
+
+/// A player controlled by a client, on the host.
+#[derive(Component)]
+pub struct Remote;
+
+/// Our own player on a client, drawn from the host's snapshots. It isn't a
+/// [`Player`], since nothing is simulated here, but the camera follows it and
+/// our input is read into its [`Actions`].
+#[derive(Component)]
+pub struct OwnReplica;
+
+#[derive(Serialize, Deserialize)]
+enum ClientMessage {
+    Join,
+    Input {
+        movement: Option<Vec2>,
+        combine: bool,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+enum HostMessage {
+    Welcome {
+        player: usize,
+    },
+    /// One part of the snapshot taken at `tick`.
+    Snapshot {
+        tick: u32,
+        /// Seconds since the host started.
+        time: f64,
+        part: u16,
+        parts: u16,
+        entities: Vec<EntityState>,
+    },
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct EntityState {
+    /// The entity on the host.
+    id: u64,
+    visual: Visual,
+    position: Vec3,
+    rotation: f32,
+    scale: Vec2,
+}
+
+/// What an entity looks like, which is all clients need to draw it.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum Visual {
+    Player { id: usize },
+    Rival,
+    Doodad { kind: DoodadKind },
+    Pickup { power_up: PowerUp },
+    Geometry,
+}
+
+/// A non-blocking UDP socket sending RON messages, like the rest of the
+/// game's data files.
+struct Socket(UdpSocket);
+
+impl Socket {
+    fn bind(address: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self(socket))
+    }
+
+    fn send(&self, message: &impl Serialize, to: SocketAddr) {
+        let result = ron::to_string(message)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                self.0
+                    .send_to(contents.as_bytes(), to)
+                    .map_err(|err| err.to_string())
+            });
+
+        if let Err(err) = result {
+            log::warn!("failed to send to {to}: {err}");
+        }
+    }
+
+    /// Everything received since the last call, skipping anything malformed.
+    fn receive<T: DeserializeOwned>(&self) -> Vec<(T, SocketAddr)> {
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        let mut messages = Vec::new();
+
+        loop {
+            let (len, from) = match self.0.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // e.g. the other side isn't listening yet, which only affects that packet
+                Err(err) => {
+                    log::debug!("failed to receive: {err}");
+                    continue;
+                }
+            };
+
+            let message = std::str::from_utf8(&buffer[..len])
+                .ok()
+                .and_then(|contents| ron::from_str(contents).ok());
+            match message {
+                Some(message) => messages.push((message, from)),
+                None => log::warn!("ignoring malformed packet from {from}"),
+            }
+        }
+
+        messages
+    }
+}
+
+struct NetHost {
+    socket: Socket,
+    clients: HashMap<SocketAddr, RemoteClient>,
+    /// The level's spawn point, for players joining later.
+    spawn: Vec2,
+    snapshot_timer: Timer,
+    tick: u32,
+}
+
+struct RemoteClient {
+    player: PlayerId,
+    root: Entity,
+    /// In seconds since startup.
+    last_heard: f64,
+}
+
+struct NetClient {
+    socket: Socket,
+    host: SocketAddr,
+    /// Our player on the host, once we've joined.
+    player: Option<PlayerId>,
+    last_heard: f64,
+    last_join: f64,
+    /// Snapshots still waiting for some of their parts, by tick.
+    partial: HashMap<u32, PartialSnapshot>,
+    latest_tick: Option<u32>,
+    /// Complete snapshots, oldest first.
+    snapshots: VecDeque<Snapshot>,
+    /// The host time being drawn, behind the latest snapshot.
+    render_time: f64,
+    /// The local entity drawing each host entity.
+    replicas: HashMap<u64, (Entity, Visual)>,
+}
+
+impl NetClient {
+    /// Add one part of the snapshot taken at `tick`, completing it once all
+    /// its parts are here.
+    fn add_snapshot_part(
+        &mut self,
+        tick: u32,
+        time: f64,
+        part: u16,
+        parts: u16,
+        entities: Vec<EntityState>,
+    ) {
+        // we've already moved past it
+        if self.latest_tick.map_or(false, |latest| tick <= latest) {
+            return;
+        }
+
+        let partial = self.partial.entry(tick).or_insert_with(|| PartialSnapshot {
+            time,
+            parts,
+            received: HashSet::new(),
+            entities: Vec::new(),
+        });
+        if !partial.received.insert(part) {
+            return;
+        }
+        partial.entities.extend(entities);
+
+        if partial.received.len() == partial.parts as usize {
+            let complete = self.partial.remove(&tick).unwrap();
+            self.snapshots.push_back(Snapshot {
+                time: complete.time,
+                entities: complete
+                    .entities
+                    .into_iter()
+                    .map(|state| (state.id, state))
+                    .collect(),
+            });
+
+            // older snapshots that are still missing parts never will be useful
+            self.latest_tick = Some(tick);
+            self.partial.retain(|partial_tick, _| *partial_tick > tick);
+        }
+    }
+}
+
+struct PartialSnapshot {
+    time: f64,
+    parts: u16,
+    received: HashSet<u16>,
+    entities: Vec<EntityState>,
+}
+
+struct Snapshot {
+    time: f64,
+    entities: HashMap<u64, EntityState>,
+}
+
+fn start_networking(
+    mut commands: Commands,
+    time: Res<Time>,
+    net_role: Option<Res<NetRole>>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let net_role = match net_role {
+        Some(net_role) => *net_role,
+        None => return,
+    };
+
+    match net_role {
+        NetRole::Host { port } => {
+            let socket = match Socket::bind(SocketAddr::from(([0, 0, 0, 0], port))) {
+                Ok(socket) => socket,
+                Err(err) => {
+                    log::error!("failed to host on port {port}: {err}");
+                    return;
+                }
+            };
+            log::info!("hosting on port {port}");
+
+            let spawn = levels
+                .get(&current_level.0)
+                .map(|level| level.spawn)
+                .unwrap_or_default();
+
+            commands.insert_resource(NetHost {
+                socket,
+                clients: HashMap::new(),
+                spawn,
+                snapshot_timer: Timer::from_seconds(SNAPSHOT_INTERVAL, true),
+                tick: 0,
+            });
+        }
+        NetRole::Client { host } => {
+            let local = if host.is_ipv4() {
+                SocketAddr::from(([0, 0, 0, 0], 0))
+            } else {
+                SocketAddr::from(([0; 16], 0))
+            };
+            let socket = match Socket::bind(local) {
+                Ok(socket) => socket,
+                Err(err) => {
+                    log::error!("failed to open a socket to join {host}: {err}");
+                    return;
+                }
+            };
+            log::info!("joining {host}");
+
+            commands.insert_resource(NetClient {
+                socket,
+                host,
+                player: None,
+                last_heard: time.seconds_since_startup(),
+                last_join: f64::NEG_INFINITY,
+                partial: HashMap::new(),
+                latest_tick: None,
+                snapshots: VecDeque::new(),
+                render_time: 0.0,
+                replicas: HashMap::new(),
+            });
+        }
+    }
+}
+
+/// On the host, let clients join and apply their input to their players.
+fn receive_inputs(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    host: Option<ResMut<NetHost>>,
+    meshes: Option<Res<MeshAssets>>,
+    mut remote_actions: Query<&mut Actions, With<Remote>>,
+) {
+    let (mut host, meshes) = match (host, meshes) {
+        (Some(host), Some(meshes)) => (host, meshes),
+        _ => return,
+    };
+    let host = &mut *host;
+    let now = time.seconds_since_startup();
+
+    // Combine is only pressed for a frame, but several inputs can arrive at
+    // once, so don't let any of them get lost.
+    for mut actions in &mut remote_actions {
+        actions.combine = false;
+    }
+
+    for (message, from) in host.socket.receive::<ClientMessage>() {
+        match message {
+            ClientMessage::Join => {
+                let player = match host.clients.get_mut(&from) {
+                    // our welcome must have gotten lost
+                    Some(client) => {
+                        client.last_heard = now;
+                        client.player
+                    }
+                    None => {
+                        let free = (settings.player_count()..MAX_PLAYERS)
+                            .map(PlayerId)
+                            .find(|id| host.clients.values().all(|client| client.player != *id));
+                        let player = match free {
+                            Some(player) => player,
+                            None => {
+                                log::warn!("{from} can't join, the game is full");
+                                continue;
+                            }
+                        };
+
+                        let position = player::spawn_position(host.spawn, player);
+                        let root =
+                            player::spawn_player_root(&mut commands, &meshes, player, position);
+                        commands.entity(root).insert(Remote);
+
+                        log::info!("{from} joined as player {}", player.0);
+                        host.clients.insert(
+                            from,
+                            RemoteClient {
+                                player,
+                                root,
+                                last_heard: now,
+                            },
+                        );
+                        player
+                    }
+                };
+
+                host.socket
+                    .send(&HostMessage::Welcome { player: player.0 }, from);
+            }
+            ClientMessage::Input { movement, combine } => {
+                let client = match host.clients.get_mut(&from) {
+                    Some(client) => client,
+                    None => continue,
+                };
+                client.last_heard = now;
+
+                if let Ok(mut actions) = remote_actions.get_mut(client.root) {
+                    actions.player_movement = movement;
+                    actions.combine |= combine;
+                }
+            }
+        }
+    }
+}
+
+/// Remove the players of clients that went quiet, along with their clusters.
+fn drop_silent_clients(mut commands: Commands, time: Res<Time>, host: Option<ResMut<NetHost>>) {
+    let mut host = match host {
+        Some(host) => host,
+        None => return,
+    };
+    let now = time.seconds_since_startup();
+
+    host.clients.retain(|address, client| {
+        let connected = now - client.last_heard < TIMEOUT;
+        if !connected {
+            log::info!("{address} timed out, removing player {}", client.player.0);
+            commands.entity(client.root).despawn_recursive();
+        }
+        connected
+    });
+}
+
+#[allow(clippy::type_complexity)]
+fn send_snapshots(
+    time: Res<Time>,
+    host: Option<ResMut<NetHost>>,
+    roots: Query<(Entity, &PlayerId, &GlobalTransform), (With<Player>, Without<Parent>)>,
+    rivals: Query<(Entity, &GlobalTransform), With<Ai>>,
+    doodads: Query<
+        (Entity, &DoodadKind, &GlobalTransform),
+        Or<(With<Doodad>, With<Player>, With<SoftAttached>)>,
+    >,
+    pickups: Query<(Entity, &Pickup, &GlobalTransform)>,
+    platforms: Query<(Entity, &GlobalTransform), With<Platform>>,
+) {
+    let mut host = match host {
+        Some(host) => host,
+        None => return,
+    };
+
+    if !host.snapshot_timer.tick(time.delta()).just_finished() || host.clients.is_empty() {
+        return;
+    }
+
+    let state = |entity: Entity, visual, transform: &GlobalTransform| {
+        let (scale, rotation, translation) = transform.to_scale_rotation_translation();
+        let (angle, _, _) = rotation.to_euler(EulerRot::ZYX);
+        EntityState {
+            id: entity.to_bits(),
+            visual,
+            position: translation,
+            rotation: angle,
+            scale: scale.truncate(),
+        }
+    };
+
+    let entities: Vec<_> = roots
+        .iter()
+        .map(|(entity, player, transform)| {
+            state(entity, Visual::Player { id: player.0 }, transform)
+        })
+        .chain(
+            rivals
+                .iter()
+                .map(|(entity, transform)| state(entity, Visual::Rival, transform)),
+        )
+        .chain(doodads.iter().map(|(entity, kind, transform)| {
+            state(entity, Visual::Doodad { kind: *kind }, transform)
+        }))
+        .chain(pickups.iter().map(|(entity, pickup, transform)| {
+            let power_up = pickup.power_up();
+            state(entity, Visual::Pickup { power_up }, transform)
+        }))
+        .chain(
+            platforms
+                .iter()
+                .map(|(entity, transform)| state(entity, Visual::Geometry, transform)),
+        )
+        .collect();
+
+    host.tick += 1;
+    let parts = entities.len().max(1).div_ceil(ENTITIES_PER_PACKET);
+
+    for part in 0..parts {
+        let start = part * ENTITIES_PER_PACKET;
+        let end = (start + ENTITIES_PER_PACKET).min(entities.len());
+        let message = HostMessage::Snapshot {
+            tick: host.tick,
+            time: time.seconds_since_startup(),
+            part: part as u16,
+            parts: parts as u16,
+            entities: entities[start..end].to_vec(),
+        };
+
+        for address in host.clients.keys() {
+            host.socket.send(&message, *address);
+        }
+    }
+}
+
+/// On clients, collect the parts of snapshots from the host.
+fn receive_snapshots(time: Res<Time>, client: Option<ResMut<NetClient>>) {
+    let mut client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    let client = &mut *client;
+
+    for (message, from) in client.socket.receive::<HostMessage>() {
+        if from != client.host {
+            continue;
+        }
+        client.last_heard = time.seconds_since_startup();
+
+        let (tick, host_time, part, parts, entities) = match message {
+            HostMessage::Welcome { player } => {
+                if client.player.is_none() {
+                    log::info!("joined {} as player {player}", client.host);
+                }
+                client.player = Some(PlayerId(player));
+                continue;
+            }
+            HostMessage::Snapshot {
+                tick,
+                time,
+                part,
+                parts,
+                entities,
+            } => (tick, time, part, parts, entities),
+        };
+
+        client.add_snapshot_part(tick, host_time, part, parts, entities);
+    }
+}
+
+/// On clients, keep asking to join until the host answers, then send it our input.
+fn send_input(
+    time: Res<Time>,
+    client: Option<ResMut<NetClient>>,
+    players: Query<&Actions, With<OwnReplica>>,
+) {
+    let mut client = match client {
+        Some(client) => client,
+        None => return,
+    };
+    let now = time.seconds_since_startup();
+
+    if client.player.is_some() && now - client.last_heard > TIMEOUT {
+        log::warn!("lost connection to {}, trying to rejoin", client.host);
+        client.player = None;
+    }
+
+    if client.player.is_none() {
+        if now - client.last_join > JOIN_INTERVAL {
+            client.last_join = now;
+            client.socket.send(&ClientMessage::Join, client.host);
+        }
+        return;
+    }
+
+    // our player only shows up with the first snapshot
+    if let Ok(actions) = players.get_single() {
+        let message = ClientMessage::Input {
+            movement: actions.player_movement,
+            combine: actions.combine,
+        };
+        client.socket.send(&message, client.host);
+    }
+}
+
+/// On clients, draw everything the host sent, interpolated between the two
+/// snapshots around the time being drawn.
+fn show_snapshots(
+    mut commands: Commands,
+    time: Res<Time>,
+    client: Option<ResMut<NetClient>>,
+    meshes: Res<MeshAssets>,
+    mut transforms: Query<&mut Transform>,
+) {
+    let mut client = match client {
+        Some(client) if client.player.is_some() => client,
+        _ => return,
+    };
+    let client = &mut *client;
+
+    let latest = match client.snapshots.back() {
+        Some(snapshot) => snapshot.time,
+        None => return,
+    };
+
+    client.render_time += time.delta_seconds_f64();
+    let target = latest - INTERPOLATION_DELAY;
+    if (client.render_time - target).abs() > MAX_CLOCK_DRIFT {
+        client.render_time = target;
+    }
+
+    // keep the last snapshot before the time being drawn, and everything after it
+    while client.snapshots.len() > 1 && client.snapshots[1].time <= client.render_time {
+        client.snapshots.pop_front();
+    }
+
+    let from = &client.snapshots[0];
+    let to = client.snapshots.get(1).unwrap_or(from);
+    let blend = if to.time > from.time {
+        ((client.render_time - from.time) / (to.time - from.time)).clamp(0.0, 1.0) as f32
+    } else {
+        1.0
+    };
+
+    // anything the host no longer has is gone
+    client.replicas.retain(|id, (entity, _)| {
+        let exists = to.entities.contains_key(id);
+        if !exists {
+            commands.entity(*entity).despawn();
+        }
+        exists
+    });
+
+    for (id, state) in &to.entities {
+        let (position, rotation) = match from.entities.get(id) {
+            Some(previous) if previous.visual == state.visual => (
+                previous.position.lerp(state.position, blend),
+                lerp_angle(previous.rotation, state.rotation, blend),
+            ),
+            _ => (state.position, state.rotation),
+        };
+        let transform = Transform::from_translation(position)
+            .with_rotation(Quat::from_rotation_z(rotation))
+            .with_scale(state.scale.extend(1.0));
+
+        match client.replicas.get(id).copied() {
+            Some((entity, visual)) if visual == state.visual => {
+                if let Ok(mut current) = transforms.get_mut(entity) {
+                    *current = transform;
+                }
+            }
+            replica => {
+                // it changed into something else
+                if let Some((entity, _)) = replica {
+                    commands.entity(entity).despawn();
+                }
+
+                let own = client.player.map(|player| player.0);
+                let entity = spawn_replica(&mut commands, &meshes, state.visual, transform, own);
+                client.replicas.insert(*id, (entity, state.visual));
+            }
+        }
+    }
+}
+
+/// Spawn something to draw an entity on the host. It has no physics, and
+/// only moves with the snapshots.
+fn spawn_replica(
+    commands: &mut Commands,
+    meshes: &MeshAssets,
+    visual: Visual,
+    transform: Transform,
+    own_player: Option<usize>,
+) -> Entity {
+    let (mesh, material) = match visual {
+        Visual::Player { id } => (
+            meshes.player.mesh.clone(),
+            meshes.player_material(PlayerId(id)),
+        ),
+        Visual::Rival => (meshes.player.mesh.clone(), meshes.ai.clone()),
+        Visual::Doodad { kind } => {
+            let asset = meshes.doodad(kind);
+            (asset.mesh.clone(), asset.material.clone())
+        }
+        Visual::Pickup { power_up } => {
+            (meshes.player.mesh.clone(), meshes.pickup_material(power_up))
+        }
+        Visual::Geometry => (meshes.floor.mesh.clone(), meshes.floor.material.clone()),
+    };
+
+    let mut replica = commands.spawn_bundle(ColorMesh2dBundle {
+        mesh: mesh.into(),
+        material,
+        transform,
+        ..default()
+    });
+
+    if let Visual::Player { id } = visual {
+        if own_player == Some(id) {
+            replica.insert(OwnReplica).insert(Actions::default());
+        }
+    }
+
+    replica.id()
+}
+
+/// Interpolate between two angles the short way around.
+fn lerp_angle(from: f32, to: f32, blend: f32) -> f32 {
+    let delta = (to - from + PI).rem_euclid(TAU) - PI;
+    from + delta * blend
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<NetRole>, String> {
+        NetRole::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn offline_without_arguments() {
+        assert!(matches!(parse(&[]), Ok(None)));
+    }
+
+    #[test]
+    fn host_on_default_port() {
+        assert!(matches!(
+            parse(&["--host"]),
+            Ok(Some(NetRole::Host { port: DEFAULT_PORT }))
+        ));
+    }
+
+    #[test]
+    fn host_on_given_port() {
+        assert!(matches!(
+            parse(&["--host", "9000"]),
+            Ok(Some(NetRole::Host { port: 9000 }))
+        ));
+    }
+
+    #[test]
+    fn join_on_default_port() {
+        let expected = SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT));
+        assert!(matches!(
+            parse(&["--join", "127.0.0.1"]),
+            Ok(Some(NetRole::Client { host })) if host == expected
+        ));
+    }
+
+    #[test]
+    fn join_on_given_port() {
+        let expected = SocketAddr::from(([127, 0, 0, 1], 9000));
+        assert!(matches!(
+            parse(&["--join", "127.0.0.1:9000"]),
+            Ok(Some(NetRole::Client { host })) if host == expected
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!(parse(&["--host", "not-a-port"]).is_err());
+        assert!(parse(&["--host", "70000"]).is_err());
+        assert!(parse(&["--join"]).is_err());
+        assert!(parse(&["--join", "127.0.0.1:not-a-port"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+    }
+
+    fn loopback() -> (Socket, Socket) {
+        let bind = || Socket::bind(SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        (bind(), bind())
+    }
+
+    fn address(socket: &Socket) -> SocketAddr {
+        socket.0.local_addr().unwrap()
+    }
+
+    /// Wait a little for `count` messages, since the sockets don't block.
+    fn receive_all<T: DeserializeOwned>(socket: &Socket, count: usize) -> Vec<(T, SocketAddr)> {
+        let mut messages = Vec::new();
+        for _ in 0..100 {
+            messages.extend(socket.receive());
+            if messages.len() >= count {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        messages
+    }
+
+    fn entity(id: u64) -> EntityState {
+        EntityState {
+            id,
+            visual: Visual::Geometry,
+            position: Vec3::new(id as f32, 0.0, 0.0),
+            rotation: 0.0,
+            scale: Vec2::ONE,
+        }
+    }
+
+    #[test]
+    fn input_reaches_host() {
+        let (host, client) = loopback();
+        let input = ClientMessage::Input {
+            movement: Some(Vec2::new(0.5, -1.0)),
+            combine: true,
+        };
+        client.send(&input, address(&host));
+
+        let messages = receive_all::<ClientMessage>(&host, 1);
+        assert_eq!(messages.len(), 1);
+        let (message, from) = &messages[0];
+        assert_eq!(*from, address(&client));
+        assert!(matches!(
+            message,
+            ClientMessage::Input { movement: Some(movement), combine: true }
+                if *movement == Vec2::new(0.5, -1.0)
+        ));
+    }
+
+    #[test]
+    fn snapshot_parts_reassemble() {
+        let (host, client_socket) = loopback();
+        let entities: Vec<_> = (0..ENTITIES_PER_PACKET as u64 * 2 + 1)
+            .map(entity)
+            .collect();
+        let chunks: Vec<_> = entities.chunks(ENTITIES_PER_PACKET).collect();
+        let parts = chunks.len() as u16;
+
+        // out of order, with a duplicate, like UDP might deliver them
+        for part in [2, 0, 0, 1] {
+            let message = HostMessage::Snapshot {
+                tick: 7,
+                time: 1.5,
+                part,
+                parts,
+                entities: chunks[part as usize].to_vec(),
+            };
+            host.send(&message, address(&client_socket));
+        }
+
+        let mut client = NetClient {
+            socket: client_socket,
+            host: address(&host),
+            player: None,
+            last_heard: 0.0,
+            last_join: 0.0,
+            partial: HashMap::new(),
+            latest_tick: None,
+            snapshots: VecDeque::new(),
+            render_time: 0.0,
+            replicas: HashMap::new(),
+        };
+        for (message, from) in receive_all::<HostMessage>(&client.socket, 4) {
+            assert_eq!(from, client.host);
+            match message {
+                HostMessage::Snapshot {
+                    tick,
+                    time,
+                    part,
+                    parts,
+                    entities,
+                } => client.add_snapshot_part(tick, time, part, parts, entities),
+                HostMessage::Welcome { .. } => panic!("unexpected welcome"),
+            }
+        }
+
+        assert_eq!(client.latest_tick, Some(7));
+        assert!(client.partial.is_empty());
+        assert_eq!(client.snapshots.len(), 1);
+        let snapshot = &client.snapshots[0];
+        assert_eq!(snapshot.time, 1.5);
+        assert_eq!(snapshot.entities.len(), entities.len());
+        for state in &entities {
+            assert_eq!(snapshot.entities[&state.id].position, state.position);
+        }
+
+        // a late part of an older snapshot is ignored
+        client.add_snapshot_part(6, 1.4, 0, 1, vec![entity(0)]);
+        assert_eq!(client.snapshots.len(), 1);
+        assert!(client.partial.is_empty());
+    }
+
+    #[test]
+    fn lerp_angle_wraps_around() {
+        // the short way from just below π to just above -π crosses π, not 0
+        let halfway = lerp_angle(PI - 0.1, -PI + 0.1, 0.5);
+        assert!((halfway.abs() - PI).abs() < 1e-5, "{halfway}");
+        let halfway = lerp_angle(-PI + 0.1, PI - 0.1, 0.5);
+        assert!((halfway.abs() - PI).abs() < 1e-5, "{halfway}");
+
+        assert!((lerp_angle(0.5, 1.5, 0.25) - 0.75).abs() < 1e-6);
+        assert!((lerp_angle(1.0, 2.0, 1.0) - 2.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,269 @@
+use std::f32::consts::{PI, TAU};
+
+use bevy::prelude::*;
+use bevy::sprite::Mesh2dHandle;
+use rand::Rng;
+
+use crate::doodad::Doodad;
+use crate::physics::Impact;
+use crate::player::DoodadAbsorbed;
+use crate::GameState;
+
+pub struct ParticlesPlugin;
+
+/// This plugin draws short-lived particle bursts for gameplay events, using a
+/// fixed pool of entities that are hidden and reused instead of despawned.
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticlePool>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(fill_pool))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(emit_absorb_particles)
+                    .with_system(emit_impact_particles)
+                    .with_system(emit_spawn_particles)
+                    .with_system(update_particles)
+                    .with_system(start_scale_pop)
+                    .with_system(update_scale_pop),
+            );
+    }
+}
+
+/// The most particles alive at once. Bursts beyond this are dropped.
+const POOL_SIZE: usize = 256;
+/// Particles are drawn above everything else in the level.
+const PARTICLE_Z: f32 = 200.0;
+const PARTICLE_SIZE: f32 = 4.0;
+/// Impacts weaker than this don't kick up any particles.
+const IMPACT_IMPULSE_MIN: f32 = 100.0;
+
+const SCALE_POP_DURATION: f32 = 0.2;
+const SCALE_POP_AMOUNT: f32 = 0.3;
+
+#[derive(Default)]
+struct ParticlePool {
+    free: Vec<Entity>,
+}
+
+#[derive(Component, Default)]
+struct Particle {
+    velocity: Vec2,
+    /// Seconds left until the particle returns to the pool.
+    remaining: f32,
+    lifetime: f32,
+}
+
+struct Burst {
+    position: Vec2,
+    color: Color,
+    count: usize,
+    speed: f32,
+    lifetime: f32,
+}
+
+impl ParticlePool {
+    fn emit(
+        &mut self,
+        burst: Burst,
+        particles: &mut Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+        materials: &mut Assets<ColorMaterial>,
+        material_handles: &Query<&Handle<ColorMaterial>, With<Particle>>,
+    ) {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..burst.count {
+            let entity = match self.free.pop() {
+                Some(entity) => entity,
+                None => return,
+            };
+
+            if let Ok((mut particle, mut transform, mut visibility)) = particles.get_mut(entity) {
+                let angle = rng.gen_range(0.0..TAU);
+                let speed = burst.speed * rng.gen_range(0.5..1.0);
+
+                particle.velocity = Vec2::new(angle.cos(), angle.sin()) * speed;
+                particle.lifetime = burst.lifetime * rng.gen_range(0.75..1.0);
+                particle.remaining = particle.lifetime;
+                transform.translation = burst.position.extend(PARTICLE_Z);
+                visibility.is_visible = true;
+            }
+
+            if let Some(material) = material_handles
+                .get(entity)
+                .ok()
+                .and_then(|handle| materials.get_mut(handle))
+            {
+                material.color = burst.color;
+            }
+        }
+    }
+}
+
+fn fill_pool(
+    mut commands: Commands,
+    mut pool: ResMut<ParticlePool>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    particles: Query<(), With<Particle>>,
+) {
+    // the pool can be gone, e.g. when the level editor clears everything spawned while playing
+    pool.free.retain(|entity| particles.get(*entity).is_ok());
+    if !pool.free.is_empty() {
+        return;
+    }
+
+    let mesh: Mesh2dHandle = meshes.add(shape::Quad::new(Vec2::ONE).into()).into();
+
+    pool.free = (0..POOL_SIZE)
+        .map(|_| {
+            commands
+                .spawn_bundle(ColorMesh2dBundle {
+                    mesh: mesh.clone(),
+                    // each particle fades independently, so it needs its own material
+                    material: materials.add(ColorMaterial::default()),
+                    transform: Transform::from_scale(Vec3::splat(PARTICLE_SIZE)),
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(Particle::default())
+                .id()
+        })
+        .collect();
+}
+
+fn emit_absorb_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut absorbed_events: EventReader<DoodadAbsorbed>,
+    mut particles: Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    material_handles: Query<&Handle<ColorMaterial>, With<Particle>>,
+) {
+    for event in absorbed_events.iter() {
+        let burst = Burst {
+            position: event.position,
+            color: Color::rgb(1.0, 0.9, 0.4),
+            count: 12,
+            speed: 120.0,
+            lifetime: 0.4,
+        };
+        pool.emit(burst, &mut particles, &mut materials, &material_handles);
+    }
+}
+
+fn emit_impact_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut impacts: EventReader<Impact>,
+    mut particles: Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    material_handles: Query<&Handle<ColorMaterial>, With<Particle>>,
+) {
+    for impact in impacts.iter() {
+        if impact.impulse < IMPACT_IMPULSE_MIN {
+            continue;
+        }
+
+        let burst = Burst {
+            position: impact.position,
+            color: Color::rgb(0.8, 0.8, 0.8),
+            count: (impact.impulse / IMPACT_IMPULSE_MIN).min(16.0) as usize,
+            speed: 80.0,
+            lifetime: 0.3,
+        };
+        pool.emit(burst, &mut particles, &mut materials, &material_handles);
+    }
+}
+
+fn emit_spawn_particles(
+    mut pool: ResMut<ParticlePool>,
+    spawned: Query<&Transform, (Added<Doodad>, Without<Particle>)>,
+    mut particles: Query<(&mut Particle, &mut Transform, &mut Visibility)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    material_handles: Query<&Handle<ColorMaterial>, With<Particle>>,
+) {
+    for transform in &spawned {
+        let burst = Burst {
+            position: transform.translation.truncate(),
+            color: Color::rgb(0.6, 0.8, 1.0),
+            count: 8,
+            speed: 60.0,
+            lifetime: 0.5,
+        };
+        pool.emit(burst, &mut particles, &mut materials, &material_handles);
+    }
+}
+
+fn update_particles(
+    time: Res<Time>,
+    mut pool: ResMut<ParticlePool>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut Visibility,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut particle, mut transform, mut visibility, material) in &mut particles {
+        if !visibility.is_visible {
+            continue;
+        }
+
+        particle.remaining -= dt;
+        if particle.remaining <= 0.0 {
+            visibility.is_visible = false;
+            pool.free.push(entity);
+            continue;
+        }
+
+        transform.translation += (particle.velocity * dt).extend(0.0);
+        // a bit of drag so bursts settle instead of flying off at constant speed
+        particle.velocity *= 1.0 - 3.0 * dt;
+
+        if let Some(material) = materials.get_mut(material) {
+            material.color.set_a(particle.remaining / particle.lifetime);
+        }
+    }
+}
+
+/// Briefly scales an entity up and back down, e.g. when it gets absorbed.
+#[derive(Component)]
+struct ScalePop {
+    elapsed: f32,
+    base_scale: Vec3,
+}
+
+fn start_scale_pop(
+    mut commands: Commands,
+    mut absorbed_events: EventReader<DoodadAbsorbed>,
+    transforms: Query<&Transform, Without<ScalePop>>,
+) {
+    for event in absorbed_events.iter() {
+        if let Ok(transform) = transforms.get(event.doodad) {
+            commands.entity(event.doodad).insert(ScalePop {
+                elapsed: 0.0,
+                base_scale: transform.scale,
+            });
+        }
+    }
+}
+
+fn update_scale_pop(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pops: Query<(Entity, &mut ScalePop, &mut Transform)>,
+) {
+    for (entity, mut pop, mut transform) in &mut pops {
+        pop.elapsed += time.delta_seconds();
+
+        if pop.elapsed >= SCALE_POP_DURATION {
+            transform.scale = pop.base_scale;
+            commands.entity(entity).remove::<ScalePop>();
+        } else {
+            let t = pop.elapsed / SCALE_POP_DURATION;
+            transform.scale = pop.base_scale * (1.0 + SCALE_POP_AMOUNT * (PI * t).sin());
+        }
+    }
+}
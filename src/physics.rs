@@ -1,21 +1,118 @@
+use std::collections::HashSet;
+
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
+#[cfg(feature = "dev")]
+use crate::console::{self, ConsoleAppExt};
 use crate::loading::MeshAsset;
 
 bitflags::bitflags! {
     pub struct CollideGroups: u32 {
-       const PLAYER = 1 << 0;
-       const DOODAD = 1 << 1;
-       const LEVEL  = 1 << 2;
+       const PLAYER  = 1 << 0;
+       const DOODAD  = 1 << 1;
+       const LEVEL   = 1 << 2;
+       const TRIGGER = 1 << 3;
+    }
+}
+
+/// Contact forces below this threshold (e.g. things resting on the floor)
+/// don't generate [`ContactForceEvent`]s.
+pub const CONTACT_FORCE_THRESHOLD: f32 = 2000.0;
+
+/// Events needed for collision feedback like impact sounds.
+pub fn feedback_events() -> (ActiveEvents, ContactForceEventThreshold) {
+    (
+        ActiveEvents::COLLISION_EVENTS | ActiveEvents::CONTACT_FORCE_EVENTS,
+        ContactForceEventThreshold(CONTACT_FORCE_THRESHOLD),
+    )
+}
+
+pub struct PhysicsPlugin;
+
+/// This plugin turns raw Rapier events into gameplay-level physics events.
+impl Plugin for PhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Impact>().add_system(detect_impacts);
+
+        #[cfg(feature = "dev")]
+        app.add_console_command("timescale", "timescale <scale>", timescale_command);
+    }
+}
+
+/// Sent when two colliders first come into contact with some force.
+pub struct Impact {
+    pub colliders: [Entity; 2],
+    /// Where the impact happened, located at the moving collider since
+    /// level geometry can be huge.
+    pub position: Vec2,
+    pub impulse: f32,
+}
+
+fn detect_impacts(
+    time: Res<Time>,
+    mut collision_events: EventReader<CollisionEvent>,
+    mut contact_force_events: EventReader<ContactForceEvent>,
+    mut impacts: EventWriter<Impact>,
+    transforms: Query<(&GlobalTransform, Option<&RigidBody>)>,
+) {
+    // Only new contacts count, otherwise stacks of resting doodads
+    // would be constantly "impacting" each other.
+    let started: HashSet<_> = collision_events
+        .iter()
+        .filter_map(|event| match *event {
+            CollisionEvent::Started(a, b, _) => Some((a.min(b), a.max(b))),
+            CollisionEvent::Stopped(..) => None,
+        })
+        .collect();
+
+    for event in contact_force_events.iter() {
+        let (a, b) = (event.collider1, event.collider2);
+        if !started.contains(&(a.min(b), a.max(b))) {
+            continue;
+        }
+
+        let position = [a, b]
+            .into_iter()
+            .filter_map(|entity| transforms.get(entity).ok())
+            .find(|(_, body)| !matches!(body, Some(RigidBody::Fixed)))
+            .map(|(transform, _)| transform.translation().truncate());
+
+        if let Some(position) = position {
+            impacts.send(Impact {
+                colliders: [a, b],
+                position,
+                // Rapier reports force, so convert it back to the impulse for this step
+                impulse: event.total_force_magnitude * time.delta_seconds(),
+            });
+        }
     }
 }
 
+/// Speed up or slow down the physics simulation, e.g. to watch collisions closely.
+#[cfg(feature = "dev")]
+fn timescale_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let scale: f32 = console::parse_arg(args, 0, "scale")?;
+    if !scale.is_finite() || scale < 0.0 {
+        return Err(format!("invalid scale: {scale}"));
+    }
+
+    let mut config = world.resource_mut::<RapierConfiguration>();
+    match &mut config.timestep_mode {
+        TimestepMode::Variable { time_scale, .. }
+        | TimestepMode::Interpolated { time_scale, .. } => {
+            *time_scale = scale;
+        }
+        TimestepMode::Fixed { .. } => return Err("physics runs at a fixed timestep".to_string()),
+    }
+    Ok(format!("physics runs at {scale}x speed"))
+}
+
 impl CollideGroups {
     pub fn player() -> CollisionGroups {
         CollisionGroups {
             memberships: Self::PLAYER.bits(),
-            filters: Self::LEVEL.bits(),
+            filters: Self::LEVEL.bits() | Self::TRIGGER.bits(),
         }
     }
 
@@ -32,6 +129,14 @@ impl CollideGroups {
             filters: Self::all().bits(),
         }
     }
+
+    /// Triggers are sensors that only detect the player.
+    pub fn trigger() -> CollisionGroups {
+        CollisionGroups {
+            memberships: Self::TRIGGER.bits(),
+            filters: Self::PLAYER.bits(),
+        }
+    }
 }
 
 #[derive(Bundle)]
@@ -39,14 +144,20 @@ pub struct PlayerBundle {
     pub collision_groups: CollisionGroups,
     pub restitution: Restitution,
     pub friction: Friction,
+    pub active_events: ActiveEvents,
+    pub contact_force_threshold: ContactForceEventThreshold,
 }
 
 impl Default for PlayerBundle {
     fn default() -> Self {
+        let (active_events, contact_force_threshold) = feedback_events();
+
         Self {
             collision_groups: CollideGroups::player(),
             restitution: Restitution::coefficient(0.5),
             friction: Friction::new(5.0),
+            active_events,
+            contact_force_threshold,
         }
     }
 }
@@ -57,6 +168,9 @@ pub struct ColliderBundle {
     pub mesh: ColorMesh2dBundle,
     pub collider: Collider,
     pub rigidbody: RigidBody,
+    pub velocity: Velocity,
+    pub active_events: ActiveEvents,
+    pub contact_force_threshold: ContactForceEventThreshold,
 }
 
 impl From<&MeshAsset> for ColliderBundle {
@@ -64,6 +178,7 @@ impl From<&MeshAsset> for ColliderBundle {
         let mesh = asset.mesh.clone().into();
         let material = asset.material.clone();
         let collider = asset.collider.clone();
+        let (active_events, contact_force_threshold) = feedback_events();
 
         Self {
             mesh: ColorMesh2dBundle {
@@ -73,6 +188,9 @@ impl From<&MeshAsset> for ColliderBundle {
             },
             collider,
             rigidbody: RigidBody::Dynamic,
+            velocity: Velocity::default(),
+            active_events,
+            contact_force_threshold,
         }
     }
 }
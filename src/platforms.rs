@@ -0,0 +1,357 @@
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::level::{CurrentLevel, Level, LEVEL_Z};
+use crate::loading::MeshAssets;
+use crate::net::{self, NetRole};
+use crate::player::Player;
+use crate::{physics, GameState};
+
+pub struct PlatformsPlugin;
+
+/// This plugin spawns and drives the level geometry that moves: platforms
+/// following paths, seesaws, conveyor belts and elevators.
+impl Plugin for PlatformsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_platforms))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(follow_paths)
+                    .with_system(run_conveyors)
+                    .with_system(run_elevators),
+            );
+    }
+}
+
+/// How quickly (per second) bodies on a conveyor are brought up to its speed.
+const CONVEYOR_GRIP: f32 = 8.0;
+const SEESAW_DENSITY: f32 = 0.5;
+
+/// A platform that moves through each point of its path in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MovingPlatform {
+    pub size: Vec2,
+    pub path: Vec<Vec2>,
+    /// Speed along the path, in pixels per second.
+    pub speed: f32,
+    /// Whether to go straight from the last point back to the first one,
+    /// rather than retracing the path in reverse.
+    #[serde(default)]
+    pub looped: bool,
+}
+
+/// A plank balanced on a pivot, which tips under weight.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Seesaw {
+    pub pivot: Vec2,
+    pub size: Vec2,
+    /// How far the seesaw can tip either way, in radians.
+    #[serde(default = "Seesaw::default_max_angle")]
+    pub max_angle: f32,
+}
+
+impl Seesaw {
+    fn default_max_angle() -> f32 {
+        0.4
+    }
+}
+
+/// A static surface that carries whatever touches it along.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conveyor {
+    pub position: Vec2,
+    pub size: Vec2,
+    #[serde(default)]
+    pub rotation: f32,
+    /// Surface speed in pixels per second, positive to the right.
+    pub speed: f32,
+}
+
+/// A platform that rises when the player gets on, waits at the top, then comes back down.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Elevator {
+    /// The resting position, at the bottom.
+    pub position: Vec2,
+    pub size: Vec2,
+    pub height: f32,
+    pub speed: f32,
+    /// Seconds spent at the top before going back down.
+    pub wait: f32,
+}
+
+#[derive(Component)]
+struct PathFollower {
+    path: Vec<Vec2>,
+    speed: f32,
+    looped: bool,
+    target: usize,
+    reversing: bool,
+}
+
+impl PathFollower {
+    fn advance(&mut self) {
+        let last = self.path.len() - 1;
+
+        if self.looped {
+            self.target = (self.target + 1) % self.path.len();
+        } else if self.reversing {
+            if self.target == 0 {
+                self.reversing = false;
+                self.target = 1;
+            } else {
+                self.target -= 1;
+            }
+        } else if self.target == last {
+            self.reversing = true;
+            self.target = last - 1;
+        } else {
+            self.target += 1;
+        }
+    }
+}
+
+/// Level geometry that is spawned and driven by this plugin.
+#[derive(Component)]
+pub struct Platform;
+
+#[derive(Component)]
+struct ConveyorBelt {
+    speed: f32,
+}
+
+#[derive(Component)]
+struct ElevatorCar {
+    bottom: f32,
+    top: f32,
+    speed: f32,
+    wait: Timer,
+    state: ElevatorState,
+}
+
+enum ElevatorState {
+    Waiting,
+    Rising,
+    AtTop,
+    Lowering,
+}
+
+fn spawn_platforms(
+    mut commands: Commands,
+    meshes: Res<MeshAssets>,
+    net_role: Option<Res<NetRole>>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        // clients only show the platforms the host sends them, since they move
+        Some(level) if !net::is_client(&net_role) => level,
+        _ => return,
+    };
+
+    for platform in &level.platforms {
+        if platform.path.len() < 2 {
+            log::warn!("ignoring moving platform with fewer than 2 points in its path");
+            continue;
+        }
+
+        let transform = Transform::from_translation(platform.path[0].extend(LEVEL_Z))
+            .with_scale(platform.size.extend(1.0));
+        let entity = spawn_geometry(
+            &mut commands,
+            &meshes,
+            transform,
+            RigidBody::KinematicPositionBased,
+        );
+
+        commands.entity(entity).insert(PathFollower {
+            path: platform.path.clone(),
+            speed: platform.speed,
+            looped: platform.looped,
+            target: 1,
+            reversing: false,
+        });
+    }
+
+    for seesaw in &level.seesaws {
+        let pivot = commands
+            .spawn_bundle(TransformBundle::from(Transform::from_translation(
+                seesaw.pivot.extend(LEVEL_Z),
+            )))
+            .insert(RigidBody::Fixed)
+            .id();
+
+        let transform = Transform::from_translation(seesaw.pivot.extend(LEVEL_Z))
+            .with_scale(seesaw.size.extend(1.0));
+        let plank = spawn_geometry(&mut commands, &meshes, transform, RigidBody::Dynamic);
+
+        let joint = RevoluteJointBuilder::new().limits([-seesaw.max_angle, seesaw.max_angle]);
+        commands
+            .entity(plank)
+            .insert(ColliderMassProperties::Density(SEESAW_DENSITY))
+            .insert(ImpulseJoint::new(pivot, joint));
+    }
+
+    for conveyor in &level.conveyors {
+        let transform = Transform::from_translation(conveyor.position.extend(LEVEL_Z))
+            .with_rotation(Quat::from_rotation_z(conveyor.rotation))
+            .with_scale(conveyor.size.extend(1.0));
+        let entity = spawn_geometry(&mut commands, &meshes, transform, RigidBody::Fixed);
+
+        commands.entity(entity).insert(ConveyorBelt {
+            speed: conveyor.speed,
+        });
+    }
+
+    for elevator in &level.elevators {
+        let transform = Transform::from_translation(elevator.position.extend(LEVEL_Z))
+            .with_scale(elevator.size.extend(1.0));
+        let entity = spawn_geometry(
+            &mut commands,
+            &meshes,
+            transform,
+            RigidBody::KinematicPositionBased,
+        );
+
+        commands.entity(entity).insert(ElevatorCar {
+            bottom: elevator.position.y,
+            top: elevator.position.y + elevator.height,
+            speed: elevator.speed,
+            wait: Timer::from_seconds(elevator.wait, false),
+            state: ElevatorState::Waiting,
+        });
+    }
+}
+
+fn spawn_geometry(
+    commands: &mut Commands,
+    meshes: &MeshAssets,
+    transform: Transform,
+    body: RigidBody,
+) -> Entity {
+    commands
+        .spawn_bundle(ColorMesh2dBundle {
+            material: meshes.floor.material.clone(),
+            mesh: meshes.floor.mesh.clone().into(),
+            transform,
+            ..default()
+        })
+        .insert(Platform)
+        .insert(body)
+        .insert(meshes.floor.collider.clone())
+        .insert(physics::CollideGroups::level())
+        .id()
+}
+
+fn follow_paths(time: Res<Time>, mut platforms: Query<(&mut PathFollower, &mut Transform)>) {
+    for (mut follower, mut transform) in &mut platforms {
+        let mut position = transform.translation.truncate();
+        let mut remaining = follower.speed * time.delta_seconds();
+
+        // bounded, in case every point of the path is in the same place
+        for _ in 0..follower.path.len() * 2 {
+            let target = follower.path[follower.target];
+            let distance = position.distance(target);
+
+            if distance > remaining {
+                position += (target - position) / distance * remaining;
+                break;
+            }
+
+            position = target;
+            remaining -= distance;
+            follower.advance();
+        }
+
+        // kinematic bodies move to wherever their transform says, carrying
+        // anything on top of them along with friction
+        transform.translation = position.extend(transform.translation.z);
+    }
+}
+
+fn run_conveyors(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    conveyors: Query<(Entity, &ConveyorBelt, &GlobalTransform)>,
+    parents: Query<&Parent>,
+    mut velocities: Query<&mut Velocity>,
+) {
+    let blend = (CONVEYOR_GRIP * time.delta_seconds()).min(1.0);
+
+    for (entity, belt, transform) in &conveyors {
+        let direction = (transform.compute_transform().rotation * Vec3::X).truncate();
+
+        for contact in rapier_context.contacts_with(entity) {
+            if !contact.has_any_active_contacts() {
+                continue;
+            }
+
+            let other = if contact.collider1() == entity {
+                contact.collider2()
+            } else {
+                contact.collider1()
+            };
+
+            // absorbed doodads are colliders attached to the player's body
+            let body = if velocities.get(other).is_ok() {
+                other
+            } else {
+                match parents.get(other) {
+                    Ok(parent) => parent.get(),
+                    Err(_) => continue,
+                }
+            };
+
+            if let Ok(mut velocity) = velocities.get_mut(body) {
+                let along = velocity.linvel.dot(direction);
+                velocity.linvel += direction * (belt.speed - along) * blend;
+            }
+        }
+    }
+}
+
+fn run_elevators(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut elevators: Query<(Entity, &mut ElevatorCar, &mut Transform)>,
+    players: Query<(), With<Player>>,
+) {
+    for (entity, mut car, mut transform) in &mut elevators {
+        let has_rider = || {
+            rapier_context.contacts_with(entity).any(|contact| {
+                contact.has_any_active_contacts()
+                    && (players.get(contact.collider1()).is_ok()
+                        || players.get(contact.collider2()).is_ok())
+            })
+        };
+
+        let step = car.speed * time.delta_seconds();
+        let y = &mut transform.translation.y;
+
+        match car.state {
+            ElevatorState::Waiting => {
+                if has_rider() {
+                    car.state = ElevatorState::Rising;
+                }
+            }
+            ElevatorState::Rising => {
+                *y = (*y + step).min(car.top);
+                if *y >= car.top {
+                    car.wait.reset();
+                    car.state = ElevatorState::AtTop;
+                }
+            }
+            ElevatorState::AtTop => {
+                if car.wait.tick(time.delta()).finished() {
+                    car.state = ElevatorState::Lowering;
+                }
+            }
+            ElevatorState::Lowering => {
+                *y = (*y - step).max(car.bottom);
+                if *y <= car.bottom {
+                    car.state = ElevatorState::Waiting;
+                }
+            }
+        }
+    }
+}
@@ -1,33 +1,231 @@
-use bevy::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use bevy::{log, prelude::*};
 use bevy_rapier2d::prelude::*;
+#[cfg(feature = "dev")]
+use rand::seq::SliceRandom;
 
 use crate::actions::Actions;
-use crate::doodad::Doodad;
+use crate::cluster::Cluster;
+#[cfg(feature = "dev")]
+use crate::console::{self, ConsoleAppExt};
+#[cfg(feature = "dev")]
+use crate::doodad::GameRng;
+use crate::doodad::{Doodad, DoodadKind};
+use crate::level::{CurrentLevel, Level};
 use crate::loading::MeshAssets;
+use crate::net::{self, NetRole};
 use crate::physics;
+use crate::powerups::ActivePowerUps;
+use crate::settings::{AttachmentMode, CombineMode, Settings};
+use crate::soft::{self, SoftAttached};
+use crate::triggers::HazardEntered;
 use crate::GameState;
 
 pub struct PlayerPlugin;
 
+pub const MAX_ANGULAR_SPEED: f32 = 30.0;
+
+/// How many players can share the screen.
+pub const MAX_PLAYERS: usize = 4;
+
+/// How far apart local players start out.
+const SPAWN_SPACING: f32 = 60.0;
+
+/// How fast doodads fly off the player when they're knocked loose.
+const RELEASE_SPEED: f32 = 150.0;
+
+/// Doodads added with the `grow` command are laid out in a spiral, starting
+/// this far from the center of the root.
+#[cfg(feature = "dev")]
+const GROW_RADIUS: f32 = 15.0;
+/// How far apart pieces added with the `grow` command are, roughly.
+#[cfg(feature = "dev")]
+const GROW_SPACING: f32 = 12.0;
+
 #[derive(Component)]
 pub struct Player;
 
+/// Labels the system that absorbs doodads, so other plugins can run around it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct CombineWithDoodads;
+
+/// Tells local players apart, on the root of each player's cluster.
+/// The first player is `PlayerId(0)`.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlayerId(pub usize);
+
+/// How the player handles, on the root of the cluster. Power-ups change these.
+#[derive(Component, Clone, Copy)]
+pub struct Movement {
+    pub max_linear_speed: f32,
+    pub angular_impulse: f32,
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Self {
+            max_linear_speed: 300.0,
+            angular_impulse: 0.01,
+        }
+    }
+}
+
+/// While on, hazards and kill planes don't cost human players any pieces.
+#[derive(Default)]
+pub struct GodMode(pub bool);
+
+/// Sent whenever a doodad becomes part of the player cluster.
+pub struct DoodadAbsorbed {
+    /// The root of the player that absorbed it.
+    pub player: Entity,
+    pub doodad: Entity,
+    pub position: Vec2,
+}
+
+/// Remembers a doodad's own material while it is part of the player,
+/// so it can be restored if the doodad is ever released again.
+#[derive(Component)]
+pub struct OriginalMaterial(pub Handle<ColorMaterial>);
+
+/// While on the player, doodads are absorbed as soon as they touch it,
+/// without having to press combine.
+#[derive(Component)]
+pub struct AutoAbsorb;
+
+/// Materials for absorbed doodads, blended toward their player's color and
+/// shared by every doodad with the same original material on the same player.
+/// Keyed by the original material and the player's material.
+#[derive(Default)]
+struct TintedMaterials(
+    HashMap<(Handle<ColorMaterial>, Handle<ColorMaterial>), Handle<ColorMaterial>>,
+);
+
+impl TintedMaterials {
+    fn get_or_add(
+        &mut self,
+        original: &Handle<ColorMaterial>,
+        player: &Handle<ColorMaterial>,
+        amount: f32,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> Handle<ColorMaterial> {
+        if amount <= 0.0 {
+            return original.clone();
+        }
+
+        self.0
+            .entry((original.clone(), player.clone()))
+            .or_insert_with(|| {
+                // the color is filled in by `update_tinted_materials`
+                let material = materials.get(original).cloned().unwrap_or_default();
+                materials.add(material)
+            })
+            .clone()
+    }
+}
+
+fn tint(color: Color, toward: Color, amount: f32) -> Color {
+    let lerp = |from: f32, to: f32| from + (to - from) * amount;
+    Color::rgba(
+        lerp(color.r(), toward.r()),
+        lerp(color.g(), toward.g()),
+        lerp(color.b(), toward.b()),
+        color.a(),
+    )
+}
+
 /// This plugin handles player related stuff like movement
 /// Player logic is only active during the State `GameState::Playing`
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_player))
+        app.add_event::<DoodadAbsorbed>()
+            .init_resource::<TintedMaterials>()
+            .init_resource::<GodMode>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_player))
             .add_system_set(
                 SystemSet::on_update(GameState::Playing)
                     .with_system(move_player)
-                    .with_system(combine_with_doodads),
+                    .with_system(combine_with_doodads.label(CombineWithDoodads))
+                    .with_system(toggle_combine_mode)
+                    .with_system(update_tinted_materials)
+                    .with_system(restore_released_materials)
+                    .with_system(strip_doodads),
             );
+
+        #[cfg(feature = "dev")]
+        app.add_console_command("grow", "grow <count>", grow_command)
+            .add_console_command("teleport", "teleport <x> <y>", teleport_command)
+            .add_console_command("god", "god", god_command);
+    }
+}
+
+fn spawn_player(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    net_role: Option<Res<NetRole>>,
+    meshes: Res<MeshAssets>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    // clients only show the players the host sends them
+    if net::is_client(&net_role) {
+        return;
     }
+
+    let spawn = levels
+        .get(&current_level.0)
+        .map(|level| level.spawn)
+        .unwrap_or_default();
+
+    for id in 0..settings.player_count() {
+        let player = PlayerId(id);
+        spawn_player_root(
+            &mut commands,
+            &meshes,
+            player,
+            spawn_position(spawn, player),
+        );
+    }
+}
+
+/// Where a player starts, given the level's spawn point. Everyone is lined
+/// up next to each other.
+pub fn spawn_position(spawn: Vec2, player: PlayerId) -> Vec2 {
+    spawn + Vec2::X * SPAWN_SPACING * player.0 as f32
 }
 
-fn spawn_player(mut commands: Commands, meshes: Res<MeshAssets>) {
+/// Spawn the root of a player's cluster, without anything attached yet.
+pub fn spawn_player_root(
+    commands: &mut Commands,
+    meshes: &MeshAssets,
+    player: PlayerId,
+    position: Vec2,
+) -> Entity {
+    let root = spawn_cluster_root(commands, meshes, meshes.player_material(player), position);
+    commands.entity(root).insert(player);
+    root
+}
+
+/// Spawn the root of a cluster that moves like the player, driven by its
+/// [`Actions`], but isn't controlled by anyone yet.
+pub fn spawn_cluster_root(
+    commands: &mut Commands,
+    meshes: &MeshAssets,
+    material: Handle<ColorMaterial>,
+    position: Vec2,
+) -> Entity {
     commands
+        .spawn_bundle(
+            physics::ColliderBundle::from(&meshes.player).with_transform(
+                Transform::from_translation(position.extend(100.0)).with_scale(Vec3::splat(30.0)),
+            ),
+        )
+        .insert(material)
         .insert(Player)
+        .insert(Actions::default())
+        .insert(Cluster::new(meshes.player.collider.clone()))
+        .insert(Movement::default())
+        .insert(ActivePowerUps::default())
         .insert(ExternalImpulse::default())
         .insert(Velocity::default())
         .insert(Damping {
@@ -35,32 +233,39 @@ fn spawn_player(mut commands: Commands, meshes: Res<MeshAssets>) {
             ..default()
         })
         .insert_bundle(physics::PlayerBundle::default())
+        .id()
 }
 
+#[allow(clippy::type_complexity)]
 fn move_player(
-    actions: Res<Actions>,
-    mut player_query: Query<(&mut Velocity, &mut ExternalImpulse), With<Player>>,
-    doodad_query: Query<(), With<Player>>,
+    mut player_query: Query<
+        (
+            Entity,
+            &Actions,
+            &Cluster,
+            &mut Velocity,
+            &mut ExternalImpulse,
+            &Movement,
+        ),
+        (With<Player>, Without<Parent>),
+    >,
+    soft_pieces: Query<&SoftAttached>,
 ) {
-    const MAX_ANGULAR_SPEED: f32 = 30.0;
-    const MAX_LINEAR_SPEED: f32 = 300.0;
-    const ANGULAR_IMPULSE: f32 = 0.01;
+    for (root, actions, cluster, mut player_vel, mut impulse, movement) in &mut player_query {
+        let player_movement = match actions.player_movement {
+            Some(player_movement) => player_movement,
+            None => continue,
+        };
 
-    if actions.player_movement.is_none() {
-        return;
-    }
-
-    // NOTE: besides just pinning X movement, it might be cool to dynamically
-    // lower the friction coefficient when we're trying to sanic-ball
-
-    // roughly scale the impulse by the number of attached doodads
-    let doodad_count = doodad_query.into_iter().count() as f32;
+        // roughly scale the impulse by the number of attached doodads
+        let soft_count = soft_pieces
+            .iter()
+            .filter(|piece| piece.root() == root)
+            .count();
+        let doodad_count = (1 + cluster.piece_count() + soft_count) as f32;
 
-    for (mut player_vel, mut impulse) in &mut player_query {
         // flip it so that left-arrow moves us left (rotates CCW)
-
-        impulse.torque_impulse =
-            actions.player_movement.unwrap().x * -ANGULAR_IMPULSE * doodad_count;
+        impulse.torque_impulse = player_movement.x * -movement.angular_impulse * doodad_count;
 
         player_vel.angvel = player_vel
             .angvel
@@ -69,42 +274,92 @@ fn move_player(
         player_vel.linvel.x = player_vel
             .linvel
             .x
-            .clamp(-MAX_LINEAR_SPEED, MAX_LINEAR_SPEED);
+            .clamp(-movement.max_linear_speed, movement.max_linear_speed);
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn combine_with_doodads(
     mut commands: Commands,
     rapier_context: Res<RapierContext>,
-    actions: Res<Actions>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut tinted_materials: ResMut<TintedMaterials>,
+    mut absorbed_events: EventWriter<DoodadAbsorbed>,
     player: Query<
-        (Entity, &GlobalTransform, &Handle<ColorMaterial>),
+        (
+            &GlobalTransform,
+            &Actions,
+            &Handle<ColorMaterial>,
+            Option<&AutoAbsorb>,
+            &Collider,
+            Option<&Cluster>,
+        ),
         (With<Player>, Without<Parent>),
     >,
-    player_colliders: Query<(&GlobalTransform, &Collider), With<Player>>,
+    player_colliders: Query<(Entity, &GlobalTransform, &Collider, Option<&Parent>), With<Player>>,
     mut doodads: Query<
-        (&GlobalTransform, &mut Transform, &mut Handle<ColorMaterial>),
+        (
+            &GlobalTransform,
+            &mut Transform,
+            &mut Handle<ColorMaterial>,
+            &Collider,
+        ),
         (With<Doodad>, Without<Player>),
     >,
 ) {
-    if !actions.combine {
-        return;
-    }
+    let root_of = |entity: Entity, parent: Option<&Parent>| parent.map_or(entity, Parent::get);
 
-    let (root_player, player_transform, player_material) = player.single();
+    // A doodad can touch several pieces of a player at once, or even several
+    // players, so collect them first to absorb each one only once, by
+    // whoever got to it first.
+    let mut touching = HashMap::new();
 
+    // Players and doodads pass through each other, so Rapier never reports
+    // contacts between them. Look for overlaps instead, every frame when
+    // combining on contact.
+    let on_contact = settings.combine_mode == CombineMode::OnContact;
     let filter = QueryFilter::only_dynamic().groups(physics::CollideGroups::doodad().into());
 
-    for (transform, collider) in &player_colliders {
+    for (entity, transform, collider, parent) in &player_colliders {
+        let root = root_of(entity, parent);
+        let combining = player
+            .get(root)
+            .map_or(false, |(_, actions, _, auto_absorb, _, _)| {
+                on_contact || actions.combine || auto_absorb.is_some()
+            });
+        if !combining {
+            continue;
+        }
+
         let transform = transform.compute_transform();
         // assume axis is always the same, since this is 2D
         let (_axis, shape_rot) = transform.rotation.to_axis_angle();
         let shape_pos = transform.translation.truncate();
 
         rapier_context.intersections_with_shape(shape_pos, shape_rot, collider, filter, |doodad| {
-            if let Ok((doodad_global_transform, mut doodad_transform, mut material)) =
-                doodads.get_mut(doodad)
-            {
+            touching.entry(doodad).or_insert(root);
+            // Match all intersections, not just the first one
+            true
+        });
+    }
+
+    for (doodad, root_player) in touching {
+        let (player_transform, _, player_material, _, player_collider, cluster) =
+            match player.get(root_player) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+
+        let (doodad_global_transform, mut doodad_transform, mut material, doodad_collider) =
+            match doodads.get_mut(doodad) {
+                Ok(doodad) => doodad,
+                Err(_) => continue,
+            };
+
+        let attached = attached_transform(player_transform, doodad_global_transform);
+        match settings.attachment_mode {
+            AttachmentMode::Rigid => {
                 commands.entity(root_player).add_child(doodad);
 
                 commands
@@ -117,20 +372,390 @@ fn combine_with_doodads(
                     .insert(physics::CollideGroups::player())
                     .insert(Player);
 
-                *material = player_material.clone();
-
-                *doodad_transform = Transform::from_matrix(
-                    player_transform.compute_matrix().inverse()
-                        * doodad_global_transform.compute_matrix(),
-                );
-                // to prevent Z-fighting with other doodads, snap almost to the player Z
-                // I would have thought that just setting it to -1.0 would work, but
-                // apparently that moves it past the clip plane...
-                // Perhaps this gets overwritten somehow in the propagation phase...
-                doodad_transform.translation.z = player_transform.translation().z - 1.0;
+                // remember which pieces it landed on, for the cluster plugin
+                if let Some(cluster) = cluster {
+                    commands.entity(doodad).insert(cluster.stuck_to(
+                        player_collider,
+                        player_transform,
+                        doodad_collider,
+                        doodad_global_transform,
+                    ));
+                }
+
+                *doodad_transform = attached;
             }
-            // Match all intersections, not just the first one
-            true
+            // stays a body of its own, held where it touched
+            AttachmentMode::Soft => soft::attach_soft(
+                &mut commands,
+                root_player,
+                player_transform,
+                doodad,
+                attached,
+            ),
+        }
+
+        commands
+            .entity(doodad)
+            .insert(OriginalMaterial(material.clone()));
+        *material = tinted_materials.get_or_add(
+            &material,
+            player_material,
+            settings.absorbed_tint,
+            &mut materials,
+        );
+
+        absorbed_events.send(DoodadAbsorbed {
+            player: root_player,
+            doodad,
+            position: doodad_global_transform.translation().truncate(),
         });
     }
 }
+
+/// Flip between combining manually and on contact, to compare how they play.
+fn toggle_combine_mode(players: Query<&Actions>, mut settings: ResMut<Settings>) {
+    if !players.iter().any(|actions| actions.toggle_combine_mode) {
+        return;
+    }
+
+    settings.combine_mode = match settings.combine_mode {
+        CombineMode::Manual => CombineMode::OnContact,
+        CombineMode::OnContact => CombineMode::Manual,
+    };
+    log::info!("combine mode is now {:?}", settings.combine_mode);
+}
+
+/// Keep tinted materials in sync with their originals, the players' colors,
+/// and the tint setting, which can all change at runtime.
+#[allow(clippy::type_complexity)]
+fn update_tinted_materials(
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut tinted_materials: ResMut<TintedMaterials>,
+    roots: Query<&Handle<ColorMaterial>, (With<Player>, Without<Parent>)>,
+    mut absorbed: Query<
+        (
+            &OriginalMaterial,
+            ChangeTrackers<OriginalMaterial>,
+            &Parent,
+            ChangeTrackers<Parent>,
+            &mut Handle<ColorMaterial>,
+        ),
+        (With<Player>, With<Parent>),
+    >,
+    mut soft_pieces: Query<
+        (
+            &OriginalMaterial,
+            ChangeTrackers<OriginalMaterial>,
+            &SoftAttached,
+            ChangeTrackers<SoftAttached>,
+            &mut Handle<ColorMaterial>,
+        ),
+        Without<Player>,
+    >,
+) {
+    let rigid = absorbed
+        .iter_mut()
+        .map(|(original, tracker, parent, parent_tracker, material)| {
+            (
+                original,
+                tracker.is_added(),
+                parent.get(),
+                parent_tracker.is_changed(),
+                material,
+            )
+        });
+    let soft = soft_pieces.iter_mut().map(
+        |(original, tracker, attached, attached_tracker, material)| {
+            (
+                original,
+                tracker.is_added(),
+                attached.root(),
+                attached_tracker.is_changed(),
+                material,
+            )
+        },
+    );
+
+    for (original, added, root, moved, mut material) in rigid.chain(soft) {
+        let player_material = match roots.get(root) {
+            Ok(player_material) => player_material,
+            Err(_) => continue,
+        };
+
+        // pieces can also be spawned already attached, e.g. by checkpoints,
+        // or taken over from another cluster
+        if settings.is_changed() || added || moved {
+            *material = tinted_materials.get_or_add(
+                &original.0,
+                player_material,
+                settings.absorbed_tint,
+                &mut materials,
+            );
+        }
+    }
+
+    for ((original, player), tinted) in &tinted_materials.0 {
+        let color = match (materials.get(original), materials.get(player)) {
+            (Some(original), Some(player)) => {
+                tint(original.color, player.color, settings.absorbed_tint)
+            }
+            _ => continue,
+        };
+
+        // only touch the asset when needed, since that re-uploads it
+        if materials
+            .get(tinted)
+            .map_or(false, |tinted| tinted.color != color)
+        {
+            if let Some(tinted) = materials.get_mut(tinted) {
+                tinted.color = color;
+            }
+        }
+    }
+}
+
+/// Doodads that are released from the player get their own material back.
+fn restore_released_materials(
+    mut commands: Commands,
+    mut released: Query<
+        (Entity, &OriginalMaterial, &mut Handle<ColorMaterial>),
+        (With<Doodad>, Without<Player>),
+    >,
+) {
+    for (entity, original, mut material) in &mut released {
+        *material = original.0.clone();
+        commands.entity(entity).remove::<OriginalMaterial>();
+    }
+}
+
+/// Where a piece at `transform` ends up relative to the root, once attached to it.
+pub fn attached_transform(root: &GlobalTransform, transform: &GlobalTransform) -> Transform {
+    let mut attached =
+        Transform::from_matrix(root.compute_matrix().inverse() * transform.compute_matrix());
+    // to prevent Z-fighting with other doodads, snap almost to the player Z
+    // I would have thought that just setting it to -1.0 would work, but
+    // apparently that moves it past the clip plane...
+    // Perhaps this gets overwritten somehow in the propagation phase...
+    attached.translation.z = root.translation().z - 1.0;
+    attached
+}
+
+/// Spawn a doodad that is already part of the player, at `transform` relative
+/// to the root, attached the way `mode` says.
+pub fn spawn_attached_doodad(
+    commands: &mut Commands,
+    meshes: &MeshAssets,
+    mode: AttachmentMode,
+    root: Entity,
+    root_transform: &GlobalTransform,
+    kind: DoodadKind,
+    transform: Transform,
+) -> Entity {
+    let asset = meshes.doodad(kind);
+
+    let doodad = match mode {
+        AttachmentMode::Rigid => {
+            let doodad = commands
+                .spawn_bundle(ColorMesh2dBundle {
+                    mesh: asset.mesh.clone().into(),
+                    material: asset.material.clone(),
+                    transform,
+                    ..default()
+                })
+                // merged into the root's collider by the cluster plugin
+                .insert(asset.collider.clone())
+                .insert(physics::CollideGroups::player())
+                .insert(Player)
+                .id();
+            commands.entity(root).add_child(doodad);
+            doodad
+        }
+        AttachmentMode::Soft => {
+            let world_transform = root_transform.mul_transform(transform);
+            let doodad = commands
+                .spawn_bundle(
+                    physics::ColliderBundle::from(asset)
+                        .with_transform(world_transform.compute_transform()),
+                )
+                .id();
+            soft::attach_soft(commands, root, root_transform, doodad, transform);
+            doodad
+        }
+    };
+
+    commands
+        .entity(doodad)
+        .insert(kind)
+        // gets swapped for the tinted material by `update_tinted_materials`
+        .insert(OriginalMaterial(asset.material.clone()));
+    doodad
+}
+
+/// Detach a doodad from the player, turning it back into a free doodad
+/// where it is now, moving with the given velocity.
+pub fn release_doodad(
+    commands: &mut Commands,
+    root: Entity,
+    doodad: Entity,
+    kind: DoodadKind,
+    transform: &GlobalTransform,
+    velocity: Velocity,
+) {
+    commands.entity(root).remove_children(&[doodad]);
+    commands
+        .entity(doodad)
+        .remove::<Player>()
+        .insert(Doodad)
+        // attached pieces only exist as part of the root's compound collider
+        .insert(kind.collider())
+        .insert_bundle(physics::feedback_events())
+        .insert(RigidBody::Dynamic)
+        .insert(physics::CollideGroups::doodad())
+        .insert(velocity)
+        .insert(transform.compute_transform());
+}
+
+fn strip_doodads(
+    mut commands: Commands,
+    god_mode: Res<GodMode>,
+    mut hazard_events: EventReader<HazardEntered>,
+    player: Query<
+        (&GlobalTransform, &Velocity, Option<&PlayerId>),
+        (With<Player>, Without<Parent>),
+    >,
+    pieces: Query<(Entity, &Parent, &GlobalTransform, &DoodadKind), With<Player>>,
+) {
+    // only strip each player once, even if they hit several hazards at once
+    let mut stripped = HashSet::new();
+
+    for HazardEntered {
+        trigger,
+        player: root,
+    } in hazard_events.iter()
+    {
+        if !stripped.insert(*root) {
+            continue;
+        }
+
+        let (root_transform, root_velocity) = match player.get(*root) {
+            Ok((_, _, Some(_))) if god_mode.0 => continue,
+            Ok((transform, velocity, _)) => (transform, velocity),
+            Err(_) => continue,
+        };
+        log::info!("hazard {trigger:?} stripped player {root:?}'s doodads");
+
+        let center = root_transform.translation().truncate();
+        for (doodad, parent, transform, kind) in &pieces {
+            if parent.get() != *root {
+                continue;
+            }
+
+            // fling each piece outward from the center of the cluster
+            let outward = (transform.translation().truncate() - center).normalize_or_zero();
+            let velocity = Velocity::linear(root_velocity.linvel + outward * RELEASE_SPEED);
+            release_doodad(&mut commands, *root, doodad, *kind, transform, velocity);
+        }
+    }
+}
+
+/// The root of the first player's cluster, for console commands.
+#[cfg(feature = "dev")]
+fn first_player(world: &mut World) -> Result<Entity, String> {
+    world
+        .query::<(Entity, &PlayerId)>()
+        .iter(world)
+        .find(|(_, id)| id.0 == 0)
+        .map(|(root, _)| root)
+        .ok_or_else(|| "there's no player".to_string())
+}
+
+/// Attach random doodads to the first player, spiraling out from the root.
+#[cfg(feature = "dev")]
+fn grow_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    console::require_state(world, GameState::Playing)?;
+    let count: usize = console::parse_arg(args, 0, "count")?;
+    let root = first_player(world)?;
+    let root_transform = *world
+        .get::<GlobalTransform>(root)
+        .ok_or("the player has no transform")?;
+    let existing = world.get::<Cluster>(root).map_or(0, Cluster::piece_count);
+
+    let kinds: Vec<_> = {
+        let mut rng = world.resource_mut::<GameRng>();
+        (0..count)
+            .map(|_| *DoodadKind::ALL.choose(&mut rng.0).unwrap())
+            .collect()
+    };
+
+    console::with_commands(world, |world, commands| {
+        let meshes = world.resource::<MeshAssets>();
+        let center = root_transform.translation().truncate();
+
+        for (index, kind) in kinds.into_iter().enumerate() {
+            // the golden angle keeps each new piece out of the others' way
+            let n = (existing + index + 1) as f32;
+            let angle = n * 2.4;
+            let offset =
+                Vec2::new(angle.cos(), angle.sin()) * (GROW_RADIUS + GROW_SPACING * n.sqrt());
+
+            let transform = GlobalTransform::from(
+                Transform::from_translation((center + offset).extend(0.0))
+                    .with_scale(kind.size().extend(1.0)),
+            );
+            spawn_attached_doodad(
+                commands,
+                meshes,
+                world.resource::<Settings>().attachment_mode,
+                root,
+                &root_transform,
+                kind,
+                attached_transform(&root_transform, &transform),
+            );
+        }
+    });
+    Ok(format!("attached {count} doodads"))
+}
+
+#[cfg(feature = "dev")]
+fn teleport_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    console::require_state(world, GameState::Playing)?;
+    let position = Vec2::new(
+        console::parse_arg(args, 0, "x")?,
+        console::parse_arg(args, 1, "y")?,
+    );
+    let root = first_player(world)?;
+
+    let mut offset = Vec3::ZERO;
+    let mut entity = world.entity_mut(root);
+    if let Some(mut transform) = entity.get_mut::<Transform>() {
+        offset = position.extend(transform.translation.z) - transform.translation;
+        transform.translation += offset;
+    }
+    if let Some(mut velocity) = entity.get_mut::<Velocity>() {
+        *velocity = Velocity::zero();
+    }
+
+    // soft pieces are bodies of their own, so they have to be brought along
+    let mut soft_pieces = world.query::<(&SoftAttached, &mut Transform, Option<&mut Velocity>)>();
+    for (piece, mut transform, velocity) in soft_pieces.iter_mut(world) {
+        if piece.root() == root {
+            transform.translation += offset;
+            if let Some(mut velocity) = velocity {
+                *velocity = Velocity::zero();
+            }
+        }
+    }
+
+    Ok(format!("teleported to {position}"))
+}
+
+#[cfg(feature = "dev")]
+fn god_command(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let mut god_mode = world.resource_mut::<GodMode>();
+    god_mode.0 = !god_mode.0;
+    Ok(format!(
+        "god mode {}",
+        if god_mode.0 { "on" } else { "off" }
+    ))
+}
@@ -0,0 +1,361 @@
+use std::collections::HashSet;
+use std::mem;
+
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::level::{CurrentLevel, Level, LEVEL_Z};
+use crate::loading::MeshAssets;
+use crate::net::{self, NetRole};
+use crate::player::{AutoAbsorb, Movement, Player};
+use crate::{physics, GameState};
+
+pub struct PowerUpsPlugin;
+
+/// This plugin spawns power-up pickups, keeps track of the effects the
+/// player has picked up, and applies them to the player's movement and
+/// physics until they wear off.
+impl Plugin for PowerUpsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_pickups))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(respawn_pickups)
+                    .with_system(collect_pickups)
+                    .with_system(expire_power_ups)
+                    .with_system(
+                        apply_power_ups
+                            .after(collect_pickups)
+                            .after(expire_power_ups),
+                    ),
+            );
+    }
+}
+
+pub const PICKUP_SIZE: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PowerUp {
+    /// Pulls in doodads within `radius` pixels and absorbs them on contact.
+    Magnet { radius: f32 },
+    /// Raises the top speed and rolls harder.
+    SpeedBoost,
+    /// Falls faster and pushes through things, at the cost of top speed.
+    Heavy,
+    /// Every piece of the player bounces off whatever it hits.
+    Bouncy,
+    /// Hardly any friction, for sliding around at speed.
+    Sanic,
+}
+
+impl PowerUp {
+    /// One of each kind of power-up, with typical parameters.
+    pub const ALL: [PowerUp; 5] = [
+        PowerUp::Magnet { radius: 200.0 },
+        PowerUp::SpeedBoost,
+        PowerUp::Heavy,
+        PowerUp::Bouncy,
+        PowerUp::Sanic,
+    ];
+
+    pub fn color(self) -> Color {
+        match self {
+            PowerUp::Magnet { .. } => Color::GOLD,
+            PowerUp::SpeedBoost => Color::CYAN,
+            PowerUp::Heavy => Color::DARK_GRAY,
+            PowerUp::Bouncy => Color::PINK,
+            PowerUp::Sanic => Color::BLUE,
+        }
+    }
+
+    /// Whether both are the same kind of power-up, ignoring their parameters.
+    pub fn same_kind(self, other: PowerUp) -> bool {
+        mem::discriminant(&self) == mem::discriminant(&other)
+    }
+}
+
+/// A power-up pickup placed in the level.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PickupSpawn {
+    pub position: Vec2,
+    pub power_up: PowerUp,
+    /// How long the effect lasts, in seconds.
+    #[serde(default = "PickupSpawn::default_duration")]
+    pub duration: f32,
+    /// If set, a new pickup appears this many seconds after this one is collected.
+    #[serde(default)]
+    pub respawn: Option<f32>,
+}
+
+impl PickupSpawn {
+    pub fn new(position: Vec2, power_up: PowerUp) -> Self {
+        Self {
+            position,
+            power_up,
+            duration: Self::default_duration(),
+            respawn: None,
+        }
+    }
+
+    fn default_duration() -> f32 {
+        8.0
+    }
+}
+
+/// The power-ups currently affecting the player, on the root of the cluster.
+#[derive(Component, Default)]
+pub struct ActivePowerUps(Vec<ActivePowerUp>);
+
+struct ActivePowerUp {
+    power_up: PowerUp,
+    /// When the effect wears off, in seconds since startup.
+    ends_at: f64,
+}
+
+impl ActivePowerUps {
+    pub fn magnet_radius(&self) -> Option<f32> {
+        self.0.iter().find_map(|active| match active.power_up {
+            PowerUp::Magnet { radius } => Some(radius),
+            _ => None,
+        })
+    }
+
+    fn has(&self, power_up: PowerUp) -> bool {
+        self.0
+            .iter()
+            .any(|active| active.power_up.same_kind(power_up))
+    }
+
+    /// Start an effect, replacing any running effect of the same kind so
+    /// picking up another one restarts the clock.
+    fn add(&mut self, power_up: PowerUp, ends_at: f64) {
+        self.0.retain(|active| !active.power_up.same_kind(power_up));
+        self.0.push(ActivePowerUp { power_up, ends_at });
+    }
+}
+
+#[derive(Component)]
+pub struct Pickup {
+    power_up: PowerUp,
+    duration: f32,
+    spawner: Entity,
+}
+
+impl Pickup {
+    pub fn power_up(&self) -> PowerUp {
+        self.power_up
+    }
+}
+
+/// Keeps a pickup coming back after it has been collected.
+#[derive(Component)]
+struct PickupSpawner {
+    spawn: PickupSpawn,
+    /// Counts down to the next pickup while there is none.
+    timer: Option<Timer>,
+}
+
+fn spawn_pickups(
+    mut commands: Commands,
+    meshes: Res<MeshAssets>,
+    net_role: Option<Res<NetRole>>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        // clients only show the pickups the host sends them
+        Some(level) if !net::is_client(&net_role) => level,
+        _ => return,
+    };
+
+    for spawn in &level.pickups {
+        let spawner = commands
+            .spawn()
+            .insert(PickupSpawner {
+                spawn: spawn.clone(),
+                timer: None,
+            })
+            .id();
+        spawn_pickup(&mut commands, &meshes, spawner, spawn);
+    }
+}
+
+fn spawn_pickup(
+    commands: &mut Commands,
+    meshes: &MeshAssets,
+    spawner: Entity,
+    spawn: &PickupSpawn,
+) {
+    commands
+        .spawn_bundle(ColorMesh2dBundle {
+            mesh: meshes.player.mesh.clone().into(),
+            material: meshes.pickup_material(spawn.power_up),
+            transform: Transform::from_translation(spawn.position.extend(LEVEL_Z + 1.0))
+                .with_scale(Vec3::splat(PICKUP_SIZE)),
+            ..default()
+        })
+        .insert(Collider::ball(0.5))
+        .insert(Sensor)
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(physics::CollideGroups::trigger())
+        .insert(Pickup {
+            power_up: spawn.power_up,
+            duration: spawn.duration,
+            spawner,
+        });
+}
+
+fn respawn_pickups(
+    mut commands: Commands,
+    time: Res<Time>,
+    meshes: Res<MeshAssets>,
+    mut spawners: Query<(Entity, &mut PickupSpawner)>,
+) {
+    for (entity, mut spawner) in &mut spawners {
+        let finished = match &mut spawner.timer {
+            Some(timer) => timer.tick(time.delta()).finished(),
+            None => continue,
+        };
+
+        if finished {
+            spawner.timer = None;
+            spawn_pickup(&mut commands, &meshes, entity, &spawner.spawn);
+        }
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut collision_events: EventReader<CollisionEvent>,
+    pickups: Query<&Pickup>,
+    mut spawners: Query<&mut PickupSpawner>,
+    players: Query<Option<&Parent>, With<Player>>,
+    mut roots: Query<&mut ActivePowerUps, (With<Player>, Without<Parent>)>,
+) {
+    // several pieces of a player, or several players, might touch the pickup at once
+    let mut collected = HashSet::new();
+
+    for event in collision_events.iter() {
+        let (a, b) = match *event {
+            CollisionEvent::Started(a, b, _) => (a, b),
+            CollisionEvent::Stopped(..) => continue,
+        };
+
+        let (entity, root) = if let Ok(parent) = players.get(a) {
+            (b, parent.map_or(a, Parent::get))
+        } else if let Ok(parent) = players.get(b) {
+            (a, parent.map_or(b, Parent::get))
+        } else {
+            continue;
+        };
+
+        let pickup = match pickups.get(entity) {
+            Ok(pickup) => pickup,
+            Err(_) => continue,
+        };
+
+        let mut active = match roots.get_mut(root) {
+            Ok(active) => active,
+            Err(_) => continue,
+        };
+
+        if !collected.insert(entity) {
+            continue;
+        }
+
+        log::info!(
+            "player {root:?} picked up {:?} for {}s",
+            pickup.power_up,
+            pickup.duration
+        );
+        commands.entity(entity).despawn_recursive();
+        active.add(
+            pickup.power_up,
+            time.seconds_since_startup() + pickup.duration as f64,
+        );
+
+        if let Ok(mut spawner) = spawners.get_mut(pickup.spawner) {
+            spawner.timer = spawner
+                .spawn
+                .respawn
+                .map(|seconds| Timer::from_seconds(seconds, false));
+        }
+    }
+}
+
+fn expire_power_ups(time: Res<Time>, mut root: Query<&mut ActivePowerUps>) {
+    let now = time.seconds_since_startup();
+
+    for mut active in &mut root {
+        // only touch the component when something expires, so that
+        // `apply_power_ups` doesn't redo its work every frame
+        if active.0.iter().any(|effect| effect.ends_at <= now) {
+            active.0.retain(|effect| {
+                let running = effect.ends_at > now;
+                if !running {
+                    log::info!("{:?} wore off", effect.power_up);
+                }
+                running
+            });
+        }
+    }
+}
+
+/// Set each player's movement and physics according to their active power-ups,
+/// whenever they change. Attached pieces are part of the root's collider,
+/// so they share its restitution and friction.
+fn apply_power_ups(
+    mut commands: Commands,
+    mut roots: Query<(Entity, &ActivePowerUps, &mut Movement), Changed<ActivePowerUps>>,
+) {
+    for (root, active, mut movement) in &mut roots {
+        let mut new_movement = Movement::default();
+        if active.has(PowerUp::SpeedBoost) {
+            new_movement.max_linear_speed *= 1.6;
+            new_movement.angular_impulse *= 1.5;
+        }
+        if active.has(PowerUp::Heavy) {
+            new_movement.max_linear_speed *= 0.8;
+            // it takes more of a push to get going
+            new_movement.angular_impulse *= 2.0;
+        }
+        *movement = new_movement;
+
+        let base = physics::PlayerBundle::default();
+        let bouncy = Restitution {
+            coefficient: 1.0,
+            combine_rule: CoefficientCombineRule::Max,
+        };
+        let slippery = Friction {
+            coefficient: 0.05,
+            combine_rule: CoefficientCombineRule::Min,
+        };
+
+        let mut root_commands = commands.entity(root);
+        root_commands
+            .insert(if active.has(PowerUp::Bouncy) {
+                bouncy
+            } else {
+                base.restitution
+            })
+            .insert(if active.has(PowerUp::Sanic) {
+                slippery
+            } else {
+                base.friction
+            });
+
+        if active.has(PowerUp::Heavy) {
+            root_commands.insert(GravityScale(2.5));
+        } else {
+            root_commands.remove::<GravityScale>();
+        }
+
+        if active.magnet_radius().is_some() {
+            root_commands.insert(AutoAbsorb);
+        } else {
+            root_commands.remove::<AutoAbsorb>();
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+
+use crate::camera::{self, PlayerCamera};
+use crate::cluster::Cluster;
+use crate::loading::{FontAssets, MeshAssets};
+use crate::net::{self, NetRole};
+use crate::player::{Player, PlayerId};
+use crate::settings::Settings;
+use crate::soft::SoftAttached;
+use crate::GameState;
+
+pub struct ScorePlugin;
+
+/// This plugin shows how many doodads each player is holding in the corner
+/// of their part of the screen, when several players compete for them.
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_scores))
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(update_scores))
+            // Place them after the cameras have moved this frame,
+            // otherwise they lag behind and jitter.
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                place_scores.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+const SCORE_FONT_SIZE: f32 = 40.0;
+/// Distance from the corner of the player's view.
+const SCORE_MARGIN: f32 = 16.0;
+/// In front of everything in the level.
+const SCORE_Z: f32 = 900.0;
+
+#[derive(Component)]
+struct Score(PlayerId);
+
+fn spawn_scores(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    net_role: Option<Res<NetRole>>,
+    font_assets: Res<FontAssets>,
+    meshes: Res<MeshAssets>,
+    materials: Res<Assets<ColorMaterial>>,
+) {
+    // playing alone, there's no one to compete with
+    let view_count = net::view_count(&settings, &net_role);
+    if view_count < 2 {
+        return;
+    }
+
+    for id in 0..view_count {
+        let player = PlayerId(id);
+        let color = materials
+            .get(&meshes.player_material(player))
+            .map_or(Color::WHITE, |material| material.color);
+
+        commands
+            .spawn_bundle(Text2dBundle {
+                text: Text::from_section(
+                    "0",
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: SCORE_FONT_SIZE,
+                        color,
+                    },
+                )
+                .with_alignment(TextAlignment::TOP_LEFT),
+                ..default()
+            })
+            .insert(Score(player))
+            // only shown in the player's own view
+            .insert(camera::view_layer(player));
+    }
+}
+
+/// The score is how many pieces each player is holding onto right now.
+fn update_scores(
+    roots: Query<(Entity, &PlayerId, &Cluster), (With<Player>, Without<Parent>)>,
+    soft_pieces: Query<&SoftAttached>,
+    mut scores: Query<(&Score, &mut Text)>,
+) {
+    for (root, player, cluster) in &roots {
+        let soft_count = soft_pieces
+            .iter()
+            .filter(|piece| piece.root() == root)
+            .count();
+        let value = (cluster.piece_count() + soft_count).to_string();
+
+        for (score, mut text) in &mut scores {
+            // only touch the text when it changes, since that lays it out again
+            if score.0 == *player && text.sections[0].value != value {
+                text.sections[0].value = value.clone();
+            }
+        }
+    }
+}
+
+/// Keep each score in the top left corner of its player's view.
+fn place_scores(
+    cameras: Query<(&PlayerCamera, &Camera, &Transform)>,
+    mut scores: Query<(&Score, &mut Transform), Without<PlayerCamera>>,
+) {
+    for (score, mut transform) in &mut scores {
+        let (camera, camera_transform) = match cameras
+            .iter()
+            .find(|(PlayerCamera(player), _, _)| *player == score.0)
+        {
+            Some((_, camera, camera_transform)) => (camera, camera_transform),
+            None => continue,
+        };
+
+        let size = match camera.logical_viewport_size() {
+            Some(size) => size,
+            None => continue,
+        };
+
+        let corner = Vec2::new(-size.x, size.y) / 2.0 + Vec2::new(SCORE_MARGIN, -SCORE_MARGIN);
+        transform.translation = (camera_transform.translation.truncate() + corner).extend(SCORE_Z);
+    }
+}
@@ -0,0 +1,314 @@
+use bevy::window::WindowMode;
+use bevy::{log, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::loading::MeshAssets;
+use crate::player::MAX_PLAYERS;
+
+/// Where the settings are persisted between runs, relative to the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+const SETTINGS_PATH: &str = "settings.ron";
+
+pub struct SettingsPlugin;
+
+/// This plugin owns the user-facing [`Settings`] resource, loading it on startup,
+/// saving it whenever it changes, and applying the window and palette options.
+/// Other plugins read the resource directly to apply the options they care about.
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // main.rs may have already loaded the settings to configure the window
+        if !app.world.contains_resource::<Settings>() {
+            app.insert_resource(Settings::load());
+        }
+
+        app.add_system(apply_window_settings)
+            .add_system(apply_palette)
+            .add_system(save_settings);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub audio: AudioSettings,
+    pub window: WindowSettings,
+    /// Multiplier for camera shake, where `0.0` disables it entirely.
+    pub screen_shake: f32,
+    pub palette: Palette,
+    /// How far absorbed doodads are tinted toward the player's color, from
+    /// `0.0` (keep their own color) to `1.0` (the same color as the player).
+    pub absorbed_tint: f32,
+    pub combine_mode: CombineMode,
+    pub attachment_mode: AttachmentMode,
+    /// How many players share the screen, from 1 to [`MAX_PLAYERS`].
+    pub local_players: usize,
+    /// The most free doodads that can exist at once, counting the recycled
+    /// ones waiting to be spawned again.
+    pub doodad_cap: usize,
+    /// The fraction of attached doodads lost each time a player respawns at
+    /// a checkpoint, from `0.0` to `1.0`.
+    pub respawn_penalty: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            audio: AudioSettings::default(),
+            window: WindowSettings::default(),
+            screen_shake: 1.0,
+            palette: Palette::default(),
+            absorbed_tint: 0.3,
+            combine_mode: CombineMode::default(),
+            attachment_mode: AttachmentMode::default(),
+            local_players: 1,
+            doodad_cap: 300,
+            respawn_penalty: 0.25,
+        }
+    }
+}
+
+impl Settings {
+    /// The number of local players, kept within the supported range.
+    pub fn player_count(&self) -> usize {
+        self.local_players.clamp(1, MAX_PLAYERS)
+    }
+
+    /// Load the settings from disk, falling back to the defaults if they are
+    /// missing or can't be parsed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load() -> Self {
+        match std::fs::read_to_string(SETTINGS_PATH) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|err| {
+                log::warn!("failed to parse {SETTINGS_PATH}, using defaults: {err}");
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    // TODO: persist to local storage on the web
+    #[cfg(target_arch = "wasm32")]
+    pub fn load() -> Self {
+        Self::default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                std::fs::write(SETTINGS_PATH, contents).map_err(|err| err.to_string())
+            });
+
+        if let Err(err) = result {
+            log::warn!("failed to save settings to {SETTINGS_PATH}: {err}");
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn save(&self) {}
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master_volume: f64,
+    pub music_volume: f64,
+    pub sfx_volume: f64,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+        }
+    }
+}
+
+impl AudioSettings {
+    /// The effective volume multiplier for music, including the master volume.
+    pub fn music(&self) -> f64 {
+        self.master_volume * self.music_volume
+    }
+
+    /// The effective volume multiplier for sound effects, including the master volume.
+    pub fn sfx(&self) -> f64 {
+        self.master_volume * self.sfx_volume
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub mode: DisplayMode,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            mode: DisplayMode::Windowed,
+            width: 800.0,
+            height: 600.0,
+        }
+    }
+}
+
+/// How the player picks up doodads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CombineMode {
+    /// Doodads are absorbed when pressing combine while touching them.
+    #[default]
+    Manual,
+    /// Doodads are absorbed as soon as they touch the player.
+    OnContact,
+}
+
+/// How absorbed doodads hold onto the player.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttachmentMode {
+    /// Doodads become a rigid part of the player's body.
+    #[default]
+    Rigid,
+    /// Doodads stay loose bodies on springy joints, and can break off.
+    Soft,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    BorderlessFullscreen,
+    Fullscreen,
+}
+
+impl From<DisplayMode> for WindowMode {
+    fn from(mode: DisplayMode) -> Self {
+        match mode {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::BorderlessFullscreen => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+}
+
+/// Color schemes for the game objects, chosen to stay distinguishable
+/// for the most common kinds of color vision deficiency.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Standard,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+pub struct PaletteColors {
+    pub player: Color,
+    /// The other local players, in order.
+    pub other_players: [Color; MAX_PLAYERS - 1],
+    /// Computer-controlled clusters.
+    pub ai: Color,
+    pub doodad: Color,
+    pub level: Color,
+}
+
+impl Palette {
+    pub fn colors(self) -> PaletteColors {
+        match self {
+            Palette::Standard => PaletteColors {
+                player: Color::RED,
+                other_players: [Color::GREEN, Color::YELLOW, Color::PURPLE],
+                ai: Color::CYAN,
+                // doodads are shown as drawn
+                doodad: Color::WHITE,
+                level: Color::DARK_GRAY,
+            },
+            // orange / blue from the Okabe-Ito palette
+            Palette::Deuteranopia | Palette::Protanopia => PaletteColors {
+                player: Color::rgb(0.9, 0.6, 0.0),
+                // yellow, reddish purple and white
+                other_players: [
+                    Color::rgb(0.95, 0.9, 0.25),
+                    Color::rgb(0.8, 0.6, 0.7),
+                    Color::WHITE,
+                ],
+                // sky blue
+                ai: Color::rgb(0.35, 0.7, 0.9),
+                doodad: Color::rgb(0.0, 0.45, 0.7),
+                level: Color::DARK_GRAY,
+            },
+            // vermillion / bluish green from the Okabe-Ito palette
+            Palette::Tritanopia => PaletteColors {
+                player: Color::rgb(0.8, 0.4, 0.0),
+                // yellow, reddish purple and sky blue
+                other_players: [
+                    Color::rgb(0.95, 0.9, 0.25),
+                    Color::rgb(0.8, 0.6, 0.7),
+                    Color::rgb(0.35, 0.7, 0.9),
+                ],
+                ai: Color::WHITE,
+                doodad: Color::rgb(0.0, 0.6, 0.5),
+                level: Color::DARK_GRAY,
+            },
+        }
+    }
+}
+
+fn apply_window_settings(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    if let Some(window) = windows.get_primary_mut() {
+        let WindowSettings {
+            mode,
+            width,
+            height,
+        } = settings.window;
+
+        window.set_mode(mode.into());
+        window.set_resolution(width, height);
+    }
+}
+
+fn apply_palette(
+    settings: Res<Settings>,
+    meshes: Option<Res<MeshAssets>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let meshes = match meshes {
+        Some(meshes) if meshes.is_added() || settings.is_changed() => meshes,
+        _ => return,
+    };
+
+    let colors = settings.palette.colors();
+    let doodads = meshes
+        .doodads
+        .values()
+        .map(|asset| (&asset.material, colors.doodad));
+    let other_players = meshes.other_players.iter().zip(colors.other_players);
+    for (handle, color) in [
+        (&meshes.player.material, colors.player),
+        (&meshes.ai, colors.ai),
+        (&meshes.floor.material, colors.level),
+    ]
+    .into_iter()
+    .chain(doodads)
+    .chain(other_players)
+    {
+        if let Some(material) = materials.get_mut(handle) {
+            material.color = color;
+        }
+    }
+}
+
+fn save_settings(settings: Res<Settings>) {
+    // the initial insertion came from disk (or defaults), so don't write it back
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_kira_audio::prelude::*;
+
+use crate::loading::AudioAssets;
+use crate::physics::Impact;
+use crate::player::DoodadAbsorbed;
+use crate::settings::Settings;
+use crate::GameState;
+
+pub struct SfxPlugin;
+
+/// This plugin plays one-shot sound effects in response to gameplay events,
+/// panned according to where they happen relative to the camera.
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<SfxChannel>()
+            .insert_resource(AbsorbCombo {
+                count: 0,
+                timer: Timer::new(COMBO_WINDOW, false),
+            })
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(play_impact_sounds)
+                    .with_system(play_absorb_sounds),
+            );
+    }
+}
+
+pub struct SfxChannel;
+
+/// Impacts with less impulse than this are silent, and anything above
+/// `IMPACT_IMPULSE_MAX` plays at full volume.
+const IMPACT_IMPULSE_MIN: f32 = 50.0;
+const IMPACT_IMPULSE_MAX: f32 = 400.0;
+const IMPACT_VOLUME: f64 = 0.6;
+
+const ABSORB_VOLUME: f64 = 0.5;
+/// Absorbing again within this window continues the combo.
+const COMBO_WINDOW: Duration = Duration::from_millis(1500);
+/// The combo pitch rises by a semitone each step, up to an octave.
+const COMBO_MAX_STEPS: u32 = 12;
+
+struct AbsorbCombo {
+    count: u32,
+    timer: Timer,
+}
+
+/// Stereo panning for a sound at `position`, where 0.0 is hard left,
+/// 0.5 is centered, and 1.0 is hard right at the edge of the screen.
+/// With split-screen, the sound is panned for the closest camera.
+fn panning(
+    position: Vec2,
+    camera: &Query<&GlobalTransform, With<Camera2d>>,
+    windows: &Windows,
+) -> f64 {
+    let camera_x = camera
+        .iter()
+        .map(|transform| transform.translation().truncate())
+        .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)))
+        .map(|camera| camera.x)
+        .unwrap_or_default();
+    let width = windows.get_primary().map_or(800.0, Window::width);
+
+    (0.5 + (position.x - camera_x) / width).clamp(0.0, 1.0) as f64
+}
+
+fn play_impact_sounds(
+    settings: Res<Settings>,
+    audio_assets: Res<AudioAssets>,
+    channel: Res<AudioChannel<SfxChannel>>,
+    windows: Res<Windows>,
+    mut impacts: EventReader<Impact>,
+    camera: Query<&GlobalTransform, With<Camera2d>>,
+) {
+    for impact in impacts.iter() {
+        if impact.impulse < IMPACT_IMPULSE_MIN {
+            continue;
+        }
+
+        let strength = ((impact.impulse - IMPACT_IMPULSE_MIN)
+            / (IMPACT_IMPULSE_MAX - IMPACT_IMPULSE_MIN))
+            .clamp(0.1, 1.0) as f64;
+
+        channel
+            .play(audio_assets.impact.clone())
+            .with_volume(IMPACT_VOLUME * strength * settings.audio.sfx())
+            // heavier hits sound a bit lower
+            .with_playback_rate(1.2 - 0.4 * strength)
+            .with_panning(panning(impact.position, &camera, &windows));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_absorb_sounds(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    audio_assets: Res<AudioAssets>,
+    channel: Res<AudioChannel<SfxChannel>>,
+    windows: Res<Windows>,
+    mut combo: ResMut<AbsorbCombo>,
+    mut absorbed_events: EventReader<DoodadAbsorbed>,
+    camera: Query<&GlobalTransform, With<Camera2d>>,
+) {
+    if combo.timer.tick(time.delta()).just_finished() {
+        combo.count = 0;
+    }
+
+    for event in absorbed_events.iter() {
+        let rate = 2.0_f64.powf(combo.count.min(COMBO_MAX_STEPS) as f64 / 12.0);
+
+        channel
+            .play(audio_assets.absorb.clone())
+            .with_volume(ABSORB_VOLUME * settings.audio.sfx())
+            .with_playback_rate(rate)
+            .with_panning(panning(event.position, &camera, &windows));
+
+        combo.count += 1;
+        combo.timer.reset();
+    }
+}
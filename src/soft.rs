@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::*;
+
+use crate::doodad::Doodad;
+use crate::player::Player;
+use crate::triggers::HazardEntered;
+use crate::{physics, GameState};
+
+pub struct SoftAttachmentPlugin;
+
+/// This plugin implements the soft attachment mode, where absorbed doodads
+/// stay dynamic bodies held onto the player by springy joints, which break
+/// when they're stretched too far. Pieces are attached with [`attach_soft`]
+/// wherever they join a cluster.
+impl Plugin for SoftAttachmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(break_stretched_joints)
+                .with_system(strip_soft_pieces),
+        );
+    }
+}
+
+/// How hard a piece is pulled back into place, per meter it is off.
+const LINEAR_STIFFNESS: f32 = 400.0;
+const LINEAR_DAMPING: f32 = 15.0;
+const ANGULAR_STIFFNESS: f32 = 200.0;
+const ANGULAR_DAMPING: f32 = 10.0;
+/// How far a piece can be pulled from where it was attached before it comes
+/// loose. Rapier's joints have no breaking force of their own, so this limit
+/// on the stretch, read off the transforms, stands in for one.
+const MAX_STRETCH: f32 = 30.0;
+/// How fast pieces fly off the player when they're knocked loose.
+const RELEASE_SPEED: f32 = 150.0;
+
+/// A doodad held onto the player by a joint instead of being part of its body.
+#[derive(Component)]
+pub struct SoftAttached {
+    root: Entity,
+    /// Where the piece belongs, relative to the root.
+    anchor: Vec3,
+}
+
+impl SoftAttached {
+    /// The root of the player this piece is attached to.
+    pub fn root(&self) -> Entity {
+        self.root
+    }
+}
+
+/// Hold `piece`, a dynamic body, onto the player at `root` with a springy
+/// joint, where `local` is where it sits relative to the root.
+pub fn attach_soft(
+    commands: &mut Commands,
+    root: Entity,
+    root_transform: &GlobalTransform,
+    piece: Entity,
+    local: Transform,
+) {
+    // Joints work in the bodies' own frames, which aren't scaled.
+    let (root_scale, _, _) = root_transform.to_scale_rotation_translation();
+    let (angle, _, _) = local.rotation.to_euler(EulerRot::ZYX);
+    let joint = GenericJointBuilder::new(JointAxesMask::empty())
+        .local_anchor1(local.translation.truncate() * root_scale.truncate())
+        .local_axis1(Vec2::from_angle(angle))
+        .local_axis2(Vec2::X)
+        .motor_position(JointAxis::X, 0.0, LINEAR_STIFFNESS, LINEAR_DAMPING)
+        .motor_position(JointAxis::Y, 0.0, LINEAR_STIFFNESS, LINEAR_DAMPING)
+        .motor_position(JointAxis::AngX, 0.0, ANGULAR_STIFFNESS, ANGULAR_DAMPING);
+
+    commands
+        .entity(piece)
+        // still part of the player, just not of its body
+        .remove::<Doodad>()
+        .insert(physics::CollideGroups::player())
+        .insert(ImpulseJoint::new(root, joint))
+        .insert(SoftAttached {
+            root,
+            anchor: local.translation,
+        });
+}
+
+fn break_stretched_joints(
+    mut commands: Commands,
+    roots: Query<&GlobalTransform>,
+    pieces: Query<(Entity, &SoftAttached, &GlobalTransform)>,
+) {
+    for (piece, attached, transform) in &pieces {
+        // the root is gone, e.g. the player was despawned
+        let root_transform = match roots.get(attached.root) {
+            Ok(root_transform) => root_transform,
+            Err(_) => {
+                detach(&mut commands, piece);
+                continue;
+            }
+        };
+
+        let anchor = root_transform.transform_point(attached.anchor).truncate();
+        if anchor.distance(transform.translation().truncate()) > MAX_STRETCH {
+            log::info!("soft piece {piece:?} broke off");
+            detach(&mut commands, piece);
+        }
+    }
+}
+
+fn strip_soft_pieces(
+    mut commands: Commands,
+    mut hazard_events: EventReader<HazardEntered>,
+    roots: Query<&GlobalTransform, (With<Player>, Without<Parent>)>,
+    mut pieces: Query<(Entity, &SoftAttached, &GlobalTransform, &mut Velocity)>,
+) {
+    let stripped: HashSet<_> = hazard_events.iter().map(|event| event.player).collect();
+
+    for (piece, attached, transform, mut velocity) in &mut pieces {
+        if !stripped.contains(&attached.root) {
+            continue;
+        }
+
+        let center = match roots.get(attached.root) {
+            Ok(root_transform) => root_transform.translation().truncate(),
+            Err(_) => continue,
+        };
+
+        // fling each piece outward from the center of the cluster
+        let outward = (transform.translation().truncate() - center).normalize_or_zero();
+        velocity.linvel += outward * RELEASE_SPEED;
+        detach(&mut commands, piece);
+    }
+}
+
+/// Let go of a soft piece, turning it back into a free doodad.
+fn detach(commands: &mut Commands, piece: Entity) {
+    commands
+        .entity(piece)
+        .remove::<ImpulseJoint>()
+        .remove::<SoftAttached>()
+        .insert(Doodad)
+        .insert(physics::CollideGroups::doodad());
+}
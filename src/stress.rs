@@ -0,0 +1,264 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use bevy::app::AppExit;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, FrameTimeDiagnosticsPlugin};
+use bevy::utils::Instant;
+use bevy::{log, prelude::*};
+use bevy_rapier2d::plugin::PhysicsStages;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::doodad::{self, Doodad, DoodadKind};
+use crate::loading::MeshAssets;
+use crate::player::{self, AutoAbsorb, CombineWithDoodads, Player, PlayerId};
+use crate::settings::Settings;
+use crate::GameState;
+
+/// How long the physics step takes each frame, in milliseconds.
+pub const PHYSICS_STEP_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x8d2c_41f0_5b6e_4a37_9e1d_2f4a_6c8b_0e53);
+
+/// How long combining with doodads takes each frame, in milliseconds.
+pub const COMBINE_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x3f1e_9b2a_7c4d_4e8f_a1b2_c3d4_e5f6_0718);
+
+/// What the stress test spawns, how long it runs, and where it writes its results.
+pub struct StressTest {
+    /// Free doodads spawned around the player.
+    pub doodads: usize,
+    /// Pieces already attached to the player.
+    pub cluster_size: usize,
+    /// How many frames to measure before exiting.
+    pub frames: u32,
+    pub output: PathBuf,
+}
+
+impl Default for StressTest {
+    fn default() -> Self {
+        Self {
+            doodads: 5000,
+            cluster_size: 500,
+            frames: 600,
+            output: PathBuf::from("stress.csv"),
+        }
+    }
+}
+
+pub struct StressTestPlugin;
+
+/// This plugin skips the menu, fills the level with doodads and a large
+/// cluster, and writes per-frame timings to a CSV file before exiting.
+/// Configure it by inserting a [`StressTest`] resource.
+impl Plugin for StressTestPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StressTest>()
+            .add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_startup_system(setup_diagnostics)
+            // time the physics step from just outside its stage
+            .add_stage_before(
+                PhysicsStages::StepSimulation,
+                StressStage::BeforeStep,
+                SystemStage::single(start_step_timer),
+            )
+            .add_stage_after(
+                PhysicsStages::StepSimulation,
+                StressStage::AfterStep,
+                // record once the step is done, so each row has this frame's timings
+                SystemStage::parallel()
+                    .with_system(stop_step_timer)
+                    .with_system(record_frame.after(stop_step_timer)),
+            )
+            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(skip_menu))
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(open_output))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn_stress_scene)
+                    // time combining from systems on either side, to keep it out of the game
+                    .with_system(start_combine_timer.before(CombineWithDoodads))
+                    .with_system(stop_combine_timer.after(CombineWithDoodads)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+enum StressStage {
+    BeforeStep,
+    AfterStep,
+}
+
+struct StepStart(Instant);
+
+struct CombineStart(Instant);
+
+struct Output {
+    writer: BufWriter<File>,
+    frame: u32,
+}
+
+fn setup_diagnostics(mut commands: Commands, mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(PHYSICS_STEP_TIME, "physics_step_time", 20).with_suffix("ms"));
+    diagnostics.add(Diagnostic::new(COMBINE_TIME, "combine_time", 20).with_suffix("ms"));
+    commands.insert_resource(StepStart(Instant::now()));
+    commands.insert_resource(CombineStart(Instant::now()));
+}
+
+fn start_step_timer(mut start: ResMut<StepStart>) {
+    start.0 = Instant::now();
+}
+
+fn stop_step_timer(start: Res<StepStart>, mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add_measurement(PHYSICS_STEP_TIME, start.0.elapsed().as_secs_f64() * 1000.0);
+}
+
+fn start_combine_timer(mut start: ResMut<CombineStart>) {
+    start.0 = Instant::now();
+}
+
+fn stop_combine_timer(start: Res<CombineStart>, mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add_measurement(COMBINE_TIME, start.0.elapsed().as_secs_f64() * 1000.0);
+}
+
+fn skip_menu(mut state: ResMut<State<GameState>>) {
+    state.set(GameState::Playing).unwrap();
+}
+
+fn open_output(mut commands: Commands, stress_test: Res<StressTest>) {
+    let file = File::create(&stress_test.output).unwrap_or_else(|err| {
+        panic!(
+            "failed to create {}: {err}",
+            stress_test.output.to_string_lossy()
+        )
+    });
+
+    let mut writer = BufWriter::new(file);
+    writeln!(
+        writer,
+        "frame,frame_time_ms,physics_step_ms,combine_ms,entities,doodads,cluster_pieces"
+    )
+    .expect("failed to write CSV header");
+
+    commands.insert_resource(Output { writer, frame: 0 });
+}
+
+/// Spawn everything once the player exists, on the first frame of play.
+fn spawn_stress_scene(
+    mut commands: Commands,
+    mut done: Local<bool>,
+    stress_test: Res<StressTest>,
+    settings: Res<Settings>,
+    meshes: Res<MeshAssets>,
+    root: Query<(Entity, &Transform), (With<PlayerId>, Without<Parent>)>,
+) {
+    if *done {
+        return;
+    }
+
+    let (root, root_transform) = match root.get_single() {
+        Ok(root) => root,
+        Err(_) => return,
+    };
+    *done = true;
+
+    let mut rng = rand::thread_rng();
+    let center = root_transform.translation.truncate();
+
+    const SPACING: f32 = 25.0;
+    let rings = (stress_test.cluster_size as f32).sqrt() as i32 + 2;
+
+    // stack free doodads in a loose grid above the cluster, so they rain down on it
+    const COLUMNS: usize = 80;
+    let bottom = rings as f32 * SPACING + 100.0;
+    for i in 0..stress_test.doodads {
+        let kind = *DoodadKind::ALL.choose(&mut rng).unwrap();
+        let cell = Vec2::new(
+            (i % COLUMNS) as f32 - COLUMNS as f32 / 2.0,
+            (i / COLUMNS) as f32,
+        );
+        let jitter = Vec2::new(rng.gen_range(-2.0..2.0), 0.0);
+        let position = center + Vec2::new(0.0, bottom) + cell * SPACING + jitter;
+        doodad::init_doodad(&mut commands.spawn(), &meshes, kind, position);
+    }
+
+    // Fill grid cells around the root, closest first, so the cluster is one
+    // solid blob. Positions are relative to the root, which is scaled up.
+    let scale = root_transform.scale.truncate();
+    let mut cells: Vec<_> = (-rings..=rings)
+        .flat_map(|x| (-rings..=rings).map(move |y| Vec2::new(x as f32, y as f32) * SPACING))
+        .filter(|cell| cell.length() > scale.x / 2.0)
+        .collect();
+    cells.sort_by(|a, b| a.length().total_cmp(&b.length()));
+
+    for cell in cells.into_iter().take(stress_test.cluster_size) {
+        let kind = *DoodadKind::ALL.choose(&mut rng).unwrap();
+        let transform = Transform::from_translation((cell / scale).extend(0.0))
+            .with_scale((kind.size() / scale).extend(1.0));
+        player::spawn_attached_doodad(
+            &mut commands,
+            &meshes,
+            settings.attachment_mode,
+            root,
+            &GlobalTransform::from(*root_transform),
+            kind,
+            transform,
+        );
+    }
+
+    // absorb on contact every frame, to measure the worst case
+    commands.entity(root).insert(AutoAbsorb);
+
+    log::info!(
+        "stress test: {} doodads, {} cluster pieces, {} frames",
+        stress_test.doodads,
+        stress_test.cluster_size,
+        stress_test.frames
+    );
+}
+
+fn record_frame(
+    output: Option<ResMut<Output>>,
+    mut exit: EventWriter<AppExit>,
+    stress_test: Res<StressTest>,
+    diagnostics: Res<Diagnostics>,
+    entities: Query<()>,
+    doodads: Query<(), With<Doodad>>,
+    pieces: Query<(), (With<Player>, With<Parent>)>,
+) {
+    // the file is opened on entering the state, which might not have applied yet
+    let mut output = match output {
+        Some(output) => output,
+        None => return,
+    };
+
+    let latest = |id| {
+        diagnostics
+            .get(id)
+            .and_then(Diagnostic::value)
+            .unwrap_or_default()
+    };
+
+    let Output { writer, frame } = &mut *output;
+    *frame += 1;
+
+    writeln!(
+        writer,
+        "{frame},{:.3},{:.3},{:.3},{},{},{}",
+        latest(FrameTimeDiagnosticsPlugin::FRAME_TIME) * 1000.0,
+        latest(PHYSICS_STEP_TIME),
+        latest(COMBINE_TIME),
+        entities.iter().count(),
+        doodads.iter().count(),
+        pieces.iter().count(),
+    )
+    .expect("failed to write CSV row");
+
+    if *frame >= stress_test.frames {
+        writer.flush().expect("failed to write CSV");
+        log::info!(
+            "stress test finished, wrote {}",
+            stress_test.output.to_string_lossy()
+        );
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+
+use bevy::{log, prelude::*};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::level::{CurrentLevel, Level, LEVEL_Z};
+use crate::loading::MeshAssets;
+use crate::player::Player;
+use crate::{physics, GameState};
+
+pub struct TriggersPlugin;
+
+/// This plugin spawns the trigger zones defined by the level, and sends an
+/// event whenever a player enters one. What happens next is up to the
+/// plugins listening for those events.
+impl Plugin for TriggersPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GoalReached>()
+            .add_event::<CheckpointReached>()
+            .add_event::<HazardEntered>()
+            .add_event::<KillPlaneEntered>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(spawn_triggers))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(detect_triggers)
+                    .with_system(log_goal_reached),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerZone {
+    pub position: Vec2,
+    pub size: Vec2,
+    pub kind: TriggerKind,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TriggerKind {
+    /// The end of the level.
+    Goal,
+    /// Saves a point to respawn at.
+    Checkpoint,
+    /// Knocks attached doodads off the player.
+    Hazard,
+    /// Respawns the player, e.g. after falling off the level.
+    KillPlane,
+}
+
+impl TriggerKind {
+    pub const ALL: [TriggerKind; 4] = [
+        TriggerKind::Goal,
+        TriggerKind::Checkpoint,
+        TriggerKind::Hazard,
+        TriggerKind::KillPlane,
+    ];
+
+    pub fn color(self) -> Option<Color> {
+        match self {
+            TriggerKind::Goal => Some(Color::rgba(0.2, 0.9, 0.3, 0.4)),
+            TriggerKind::Checkpoint => Some(Color::rgba(0.9, 0.9, 0.2, 0.3)),
+            TriggerKind::Hazard => Some(Color::rgba(0.9, 0.2, 0.1, 0.4)),
+            // kill planes are off-screen anyway
+            TriggerKind::KillPlane => None,
+        }
+    }
+}
+
+// Each event has the root of the player that entered the trigger.
+
+pub struct GoalReached {
+    pub trigger: Entity,
+    pub player: Entity,
+}
+
+pub struct CheckpointReached {
+    pub trigger: Entity,
+    pub player: Entity,
+    pub position: Vec2,
+}
+
+pub struct HazardEntered {
+    pub trigger: Entity,
+    pub player: Entity,
+}
+
+pub struct KillPlaneEntered {
+    pub trigger: Entity,
+    pub player: Entity,
+}
+
+fn spawn_triggers(
+    mut commands: Commands,
+    meshes: Res<MeshAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+) {
+    let level = match levels.get(&current_level.0) {
+        Some(level) => level,
+        None => return,
+    };
+
+    for zone in &level.triggers {
+        // drawn just in front of the level geometry
+        let transform = Transform::from_translation(zone.position.extend(LEVEL_Z + 1.0))
+            .with_scale(zone.size.extend(1.0));
+
+        let mut entity = commands.spawn_bundle(TransformBundle::from(transform));
+        if let Some(color) = zone.kind.color() {
+            entity.insert_bundle(ColorMesh2dBundle {
+                mesh: meshes.floor.mesh.clone().into(),
+                material: materials.add(ColorMaterial::from(color)),
+                transform,
+                ..default()
+            });
+        }
+
+        entity
+            .insert(Collider::cuboid(0.5, 0.5))
+            .insert(Sensor)
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            .insert(physics::CollideGroups::trigger())
+            .insert(zone.kind);
+    }
+}
+
+fn detect_triggers(
+    mut collision_events: EventReader<CollisionEvent>,
+    triggers: Query<(&TriggerKind, &GlobalTransform)>,
+    players: Query<Option<&Parent>, With<Player>>,
+    mut goal_events: EventWriter<GoalReached>,
+    mut checkpoint_events: EventWriter<CheckpointReached>,
+    mut hazard_events: EventWriter<HazardEntered>,
+    mut kill_plane_events: EventWriter<KillPlaneEntered>,
+) {
+    // Several pieces of a player can enter the same trigger at once,
+    // but it should only fire once for each player.
+    let mut entered = HashSet::new();
+
+    for event in collision_events.iter() {
+        let (a, b) = match *event {
+            CollisionEvent::Started(a, b, _) => (a, b),
+            CollisionEvent::Stopped(..) => continue,
+        };
+
+        let (trigger, player) = if let Ok(parent) = players.get(a) {
+            (b, parent.map_or(a, Parent::get))
+        } else if let Ok(parent) = players.get(b) {
+            (a, parent.map_or(b, Parent::get))
+        } else {
+            continue;
+        };
+
+        let (kind, transform) = match triggers.get(trigger) {
+            Ok(trigger) => trigger,
+            Err(_) => continue,
+        };
+
+        if !entered.insert((trigger, player)) {
+            continue;
+        }
+
+        match kind {
+            TriggerKind::Goal => goal_events.send(GoalReached { trigger, player }),
+            TriggerKind::Checkpoint => checkpoint_events.send(CheckpointReached {
+                trigger,
+                player,
+                position: transform.translation().truncate(),
+            }),
+            TriggerKind::Hazard => hazard_events.send(HazardEntered { trigger, player }),
+            TriggerKind::KillPlane => kill_plane_events.send(KillPlaneEntered { trigger, player }),
+        }
+    }
+}
+
+fn log_goal_reached(mut goal_events: EventReader<GoalReached>) {
+    for GoalReached { trigger, player } in goal_events.iter() {
+        log::info!("player {player:?} reached goal {trigger:?}!");
+    }
+}